@@ -0,0 +1,155 @@
+use crate::{
+    bar::Bar,
+    check::check,
+    collectors::{CollectorResult, Registry},
+    config::{CommandItem, Config},
+    input::manage_clicks,
+    reload::manage_reload,
+    state::ProtectedState,
+    unix::{manage_unix_socket, SOCKET_PATH},
+};
+use anyhow::Result;
+use std::path::PathBuf;
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedSender},
+        watch,
+    },
+};
+
+// prints the problems with the configuration, returning whether there were any
+fn check_config(filename: PathBuf, registry: &Registry) -> bool {
+    let config = match Config::load(filename.clone()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}: {}", filename.display(), e);
+            return false;
+        }
+    };
+
+    let source = std::fs::read_to_string(&filename).unwrap_or_default();
+    let problems = check(&config, registry);
+    for problem in &problems {
+        match problem.locate(&source) {
+            Some((line, column)) => eprintln!(
+                "{}:{}:{}: {}",
+                filename.display(),
+                line,
+                column,
+                problem.message
+            ),
+            None => eprintln!(
+                "{}: block '{}': {}",
+                filename.display(),
+                problem.block,
+                problem.message
+            ),
+        }
+    }
+
+    if problems.is_empty() {
+        println!("{}: ok", filename.display());
+    }
+
+    problems.is_empty()
+}
+
+fn config_file() -> PathBuf {
+    std::env::var("YAIB_CONFIG")
+        .map(|x| x.into())
+        .unwrap_or_else(|_| {
+            dirs::config_local_dir()
+                .map(|x| x.join("yaib"))
+                .unwrap_or(dirs::home_dir().unwrap_or("/".into()))
+                .join("yaib.config.yaml")
+        })
+}
+
+async fn write_block(s: &str) -> Result<()> {
+    let mut stream = tokio::net::UnixStream::connect(SOCKET_PATH).await?;
+    stream.write_all(s.as_bytes()).await?;
+    Ok(())
+}
+
+// Runs yaib with the arguments it was started with, collecting blocks with the collectors of the
+// registry. Blocks written to the socket are sent to commands, whose receiver the registry's
+// `dynamic` collector reads, as with Registry::new. A crate adding its own block types builds the
+// registry, registers them and calls this from its main.
+pub async fn run(registry: Registry, commands: UnboundedSender<CommandItem>) -> Result<()> {
+    let mut args = std::env::args();
+
+    if let Some(cmd) = args.nth(1) {
+        if cmd == "write-block" {
+            if let Some(s) = args.next() {
+                let _: CommandItem = serde_json::from_str(&s)?; // just test that it parses
+                write_block(&s).await?;
+            }
+
+            return Ok(());
+        } else if cmd == "clear-block" {
+            if let Some(name) = args.next() {
+                write_block(&serde_json::json!({"name": name, "delete": true}).to_string()).await?;
+            }
+
+            return Ok(());
+        } else if cmd == "check" || cmd == "validate" {
+            let filename = args.next().map(PathBuf::from).unwrap_or_else(config_file);
+            if !check_config(filename, &registry) {
+                std::process::exit(1);
+            }
+
+            return Ok(());
+        }
+    }
+
+    let filename = config_file();
+    let mut config = Config::load(filename.clone())?;
+    config.validate(&registry)?;
+
+    let (s_collection, r_collection) = unbounded_channel();
+    let (s_redraw, r_redraw) = unbounded_channel();
+    let (s_click, mut r_click) = unbounded_channel();
+    let (s_result, mut r_result) = unbounded_channel::<CollectorResult>();
+    let (s_config, mut r_config) = watch::channel(config.clone());
+    let state = ProtectedState::default();
+    let mut bar = Bar::new(state.clone());
+
+    let s_commands = commands.clone();
+    tokio::spawn(async move { manage_unix_socket(s_commands).await });
+    let c = r_config.clone();
+    tokio::spawn(async move {
+        bar.emit_status(c, std::io::stdout(), r_collection, r_redraw)
+            .await
+            .unwrap()
+    });
+    let c = r_config.clone();
+    let s = state.clone();
+    tokio::spawn(async move { manage_clicks(s, c, commands, s_redraw, s_click).await });
+    let r = registry.clone();
+    tokio::spawn(async move { manage_reload(filename, r, s_config, state).await });
+
+    loop {
+        config
+            .launch_collectors(&registry, s_collection.clone(), s_result.clone())
+            .await?;
+
+        // sleep until the next collector is due, or something changes what is due
+        let next = config.next_update().map_or(std::time::Duration::MAX, |x| {
+            (x - chrono::Local::now()).to_std().unwrap_or_default()
+        });
+
+        tokio::select! {
+            _ = tokio::time::sleep(next) => {}
+            Ok(_) = r_config.changed() => {
+                config.reloaded(r_config.borrow_and_update().clone());
+            }
+            Some((name, result)) = r_result.recv() => {
+                config.record_result(&name, result);
+            }
+            Some(click) = r_click.recv() => {
+                config.clicked(click);
+            }
+        }
+    }
+}
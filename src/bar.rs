@@ -1,15 +1,29 @@
-use crate::{collectors::Collection, config::Config};
+use crate::{
+    collectors::{Collection, STALE_COLOR},
+    config::Config,
+};
 use anyhow::Result;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::BTreeMap;
-use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::{
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver},
+        watch,
+    },
+    time::Instant,
+};
 
 pub const NAME_PAGE_UP: &str = "yaib-page-up";
 pub const NAME_PAGE_DOWN: &str = "yaib-page-down";
+pub const NAME_CONFIG_ERROR: &str = "yaib-config-error";
 
 #[derive(Debug, Clone, Default)]
 pub struct Bar {
     state: BTreeMap<String, Block>,
+    // the last collection of each block, to draw it again when a click changes how it is shown
+    collections: BTreeMap<String, Collection>,
+    // when blocks written with a ttl expire, and whether they are then greyed out
+    expiry: BTreeMap<String, (Instant, bool)>,
     internal_state: crate::state::ProtectedState,
 }
 
@@ -17,10 +31,72 @@ impl Bar {
     pub fn new(internal_state: crate::state::ProtectedState) -> Self {
         Self {
             state: BTreeMap::default(),
+            collections: BTreeMap::default(),
+            expiry: BTreeMap::default(),
             internal_state,
         }
     }
 
+    async fn update(&mut self, collection: Collection) -> Result<()> {
+        let name = collection.name();
+
+        if collection.delete() {
+            // clearing an error keeps whatever the collector showed since
+            if collection.failed() && !self.collections.get(&name).is_some_and(|x| x.failed()) {
+                return Ok(());
+            }
+
+            self.expiry.remove(&name);
+            self.state.remove(&name);
+            self.collections.remove(&name);
+            return Ok(());
+        }
+
+        self.expiry.remove(&name);
+        if let Some(ttl) = collection.ttl() {
+            self.expiry
+                .insert(name.clone(), (Instant::now() + ttl, collection.stale()));
+        }
+
+        let block = collection.to_block(self.internal_state.clone()).await?;
+        self.state.insert(name.clone(), block);
+        self.collections.insert(name, collection);
+        Ok(())
+    }
+
+    async fn redraw(&mut self) -> Result<()> {
+        for (name, collection) in &self.collections {
+            let block = collection.redraw(self.internal_state.clone()).await?;
+            self.state.insert(name.clone(), block);
+        }
+
+        Ok(())
+    }
+
+    // removes the blocks whose ttl has passed, or greys them out if they are to be kept
+    fn expire(&mut self) {
+        let now = Instant::now();
+        let expired = self
+            .expiry
+            .iter()
+            .filter(|(_, (at, _))| *at <= now)
+            .map(|(name, (_, stale))| (name.clone(), *stale))
+            .collect::<Vec<(String, bool)>>();
+
+        for (name, stale) in expired {
+            self.expiry.remove(&name);
+            // a stale block stays as it was when it expired, greyed out
+            self.collections.remove(&name);
+
+            if !stale {
+                self.state.remove(&name);
+            } else if let Some(block) = self.state.get_mut(&name) {
+                block.color = Some(STALE_COLOR.to_string());
+                block.urgent = None;
+            }
+        }
+    }
+
     async fn add_page_blocks(&self, v: &mut Vec<Block>, pages: usize) {
         let page = self.internal_state.lock().await.page;
 
@@ -41,6 +117,21 @@ impl Bar {
         }
     }
 
+    async fn add_error_block(&self, v: &mut Vec<Block>) {
+        if let Some(error) = &self.internal_state.lock().await.config_error {
+            v.insert(
+                0,
+                Block {
+                    name: Some(NAME_CONFIG_ERROR.to_string()),
+                    full_text: format!("config error: {}", error.replace('\n', " ")),
+                    color: Some("#FF6666".to_string()),
+                    urgent: Some(true),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
     pub async fn write_blocks(
         &self,
         mut w: impl std::io::Write + Send + 'static,
@@ -57,9 +148,10 @@ impl Bar {
 
     pub async fn emit_status(
         &mut self,
-        config: Config,
+        config: watch::Receiver<Config>,
         mut w: impl std::io::Write + Send + 'static,
         mut data: UnboundedReceiver<Collection>,
+        mut redraw: UnboundedReceiver<()>,
     ) -> Result<()> {
         serde_json::to_writer(
             &mut w,
@@ -76,31 +168,57 @@ impl Bar {
         let obj = self.clone();
         tokio::spawn(async move { obj.write_blocks(w, r).await.unwrap() });
 
-        let mut last_send = chrono::Local::now() - config.update_interval();
         let mut last_sent = Vec::new();
 
-        while let Some(collection) = data.recv().await {
-            let block = collection.to_block(self.internal_state.clone()).await?;
-            self.state.insert(collection.name(), block);
-
-            let now = chrono::Local::now();
-            if last_send + config.update_interval() < now {
-                let mut v = Vec::new();
-                let items = &config.pages()[self.internal_state.lock().await.page].items();
-                for item in items {
-                    if let Some(block) = self.state.get(&item.name) {
-                        v.push(block.clone())
+        loop {
+            let expiry = self.expiry.values().map(|(at, _)| *at).min();
+            let expired = tokio::time::sleep_until(expiry.unwrap_or_else(Instant::now));
+
+            tokio::select! {
+                collection = data.recv() => {
+                    let Some(collection) = collection else {
+                        break;
+                    };
+                    self.update(collection).await?;
+
+                    // collectors due at the same time are written out together
+                    while let Ok(collection) = data.try_recv() {
+                        self.update(collection).await?;
                     }
                 }
+                _ = expired, if expiry.is_some() => {
+                    self.expire();
+                }
+                Some(_) = redraw.recv() => {
+                    while redraw.try_recv().is_ok() {}
+                    self.redraw().await?;
+                }
+            }
 
-                self.add_page_blocks(&mut v, config.pages().len() - 1).await;
+            // the configuration can be replaced by a reload at any time
+            let config = config.borrow().clone();
 
-                if !last_sent.eq(&v) {
-                    s.send(v.clone())?;
-                    last_send = now;
-                    last_sent = v;
+            let mut v = Vec::new();
+            let page = self.internal_state.lock().await.page;
+            let items = config
+                .pages()
+                .get(page)
+                .map(|x| x.items())
+                .unwrap_or_default();
+            for item in items {
+                if let Some(block) = self.state.get(&item.name) {
+                    v.push(block.clone())
                 }
             }
+
+            self.add_page_blocks(&mut v, config.pages().len().saturating_sub(1))
+                .await;
+            self.add_error_block(&mut v).await;
+
+            if !last_sent.eq(&v) {
+                s.send(v.clone())?;
+                last_sent = v;
+            }
         }
 
         Ok(())
@@ -118,15 +236,25 @@ pub struct Header {
     click_events: Option<bool>,
 }
 
+// i3bar takes a width in pixels, or a string whose width is used instead
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum MinWidth {
+    Pixels(u32),
+    Text(String),
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Block {
+    // commands may leave it out, as they give their text as a value
+    #[serde(default)]
     pub full_text: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub short_text: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub color: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub min_width: Option<String>,
+    pub min_width: Option<MinWidth>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub align: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -155,6 +283,27 @@ pub struct Block {
     pub border_right: Option<String>,
 }
 
+impl Block {
+    // sets the fields that are set in other, except the name and text which the block keeps
+    pub fn merge(&mut self, other: &Block) {
+        self.short_text = other.short_text.clone().or(self.short_text.take());
+        self.color = other.color.clone().or(self.color.take());
+        self.min_width = other.min_width.clone().or(self.min_width.take());
+        self.align = other.align.clone().or(self.align.take());
+        self.instance = other.instance.clone().or(self.instance.take());
+        self.urgent = other.urgent.or(self.urgent);
+        self.separator = other.separator.or(self.separator);
+        self.separator_block_width = other.separator_block_width.or(self.separator_block_width);
+        self.markup = other.markup.clone().or(self.markup.take());
+        self.background = other.background.clone().or(self.background.take());
+        self.border = other.border.clone().or(self.border.take());
+        self.border_top = other.border_top.clone().or(self.border_top.take());
+        self.border_bottom = other.border_bottom.clone().or(self.border_bottom.take());
+        self.border_left = other.border_left.clone().or(self.border_left.take());
+        self.border_right = other.border_right.clone().or(self.border_right.take());
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Click {
     pub name: String,
@@ -170,3 +319,143 @@ pub struct Click {
     pub height: u16,
     pub modifiers: Vec<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collectors::test::item, state::ProtectedState};
+
+    fn usage(name: &str, usage: u64) -> Collection {
+        let yaml = format!(
+            "name: {}\ntype: cpu\non_click:\n  left:\n    formats: [\"{{usage}}%\"]",
+            name
+        );
+        Collection::new(
+            item(&yaml),
+            "cpu {usage}",
+            vec![("usage".into(), usage.into())],
+            usage,
+        )
+    }
+
+    #[tokio::test]
+    async fn clicks_redraw_without_recording() {
+        let state = ProtectedState::default();
+        let mut bar = Bar::new(state.clone());
+
+        bar.update(usage("cpu", 5)).await.unwrap();
+        assert_eq!(bar.state["cpu"].full_text, "cpu 5");
+
+        state.lock().await.formats.insert("cpu".to_string(), 1);
+        bar.redraw().await.unwrap();
+        assert_eq!(bar.state["cpu"].full_text, "5%");
+        assert_eq!(state.lock().await.history("cpu"), vec![5]);
+    }
+
+    #[test]
+    fn min_width_is_pixels_or_text() {
+        let block: Block =
+            serde_json::from_str(r#"{"name":"x","full_text":"v","min_width":100}"#).unwrap();
+        assert_eq!(block.min_width, Some(MinWidth::Pixels(100)));
+        assert!(serde_json::to_string(&block)
+            .unwrap()
+            .contains(r#""min_width":100"#));
+
+        let block: Block =
+            serde_json::from_str(r#"{"name":"x","full_text":"v","min_width":"100%"}"#).unwrap();
+        assert_eq!(block.min_width, Some(MinWidth::Text("100%".to_string())));
+
+        let command: crate::config::CommandItem =
+            serde_json::from_str(r#"{"name":"x","value":"v","min_width":100}"#).unwrap();
+        assert_eq!(command.block.min_width, Some(MinWidth::Pixels(100)));
+    }
+
+    #[tokio::test]
+    async fn success_clears_errors() {
+        let mut bar = Bar::new(ProtectedState::default());
+        let cpu = || item("name: cpu\ntype: cpu");
+
+        bar.update(Collection::error(cpu(), "failed"))
+            .await
+            .unwrap();
+        assert_eq!(bar.state["cpu"].full_text, "cpu: failed");
+        bar.update(Collection::cleared(cpu())).await.unwrap();
+        assert!(!bar.state.contains_key("cpu"));
+
+        bar.update(Collection::error(cpu(), "failed"))
+            .await
+            .unwrap();
+        bar.update(usage("cpu", 5)).await.unwrap();
+        bar.update(Collection::cleared(cpu())).await.unwrap();
+        assert_eq!(bar.state["cpu"].full_text, "cpu 5");
+    }
+
+    // a block written over the unix socket, shown for ttl seconds
+    fn note(text: &str, ttl: Option<u64>, stale: bool) -> Collection {
+        Collection::text(item("name: note\ntype: dynamic"), text.to_string(), 0)
+            .with_ttl(ttl.map(std::time::Duration::from_secs), stale)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ttl_removes() {
+        let mut bar = Bar::new(ProtectedState::default());
+        bar.update(note("hi", Some(10), false)).await.unwrap();
+
+        tokio::time::advance(std::time::Duration::from_secs(9)).await;
+        bar.expire();
+        assert_eq!(bar.state["note"].full_text, "hi");
+
+        tokio::time::advance(std::time::Duration::from_secs(1)).await;
+        bar.expire();
+        assert!(!bar.state.contains_key("note"));
+        assert!(bar.expiry.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ttl_greys_out_stale() {
+        let mut bar = Bar::new(ProtectedState::default());
+        bar.update(note("hi", Some(10), true).with_urgent(true))
+            .await
+            .unwrap();
+
+        tokio::time::advance(std::time::Duration::from_secs(10)).await;
+        bar.expire();
+        assert_eq!(bar.state["note"].full_text, "hi");
+        assert_eq!(bar.state["note"].color.as_deref(), Some(STALE_COLOR));
+        assert_eq!(bar.state["note"].urgent, None);
+        // it is not drawn again, which would bring its color back
+        assert!(!bar.collections.contains_key("note"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rewriting_resets_ttl() {
+        let mut bar = Bar::new(ProtectedState::default());
+        bar.update(note("hi", Some(10), false)).await.unwrap();
+
+        tokio::time::advance(std::time::Duration::from_secs(8)).await;
+        bar.update(note("again", Some(10), false)).await.unwrap();
+
+        tokio::time::advance(std::time::Duration::from_secs(8)).await;
+        bar.expire();
+        assert_eq!(bar.state["note"].full_text, "again");
+
+        // without a ttl it stays
+        bar.update(note("kept", None, false)).await.unwrap();
+        tokio::time::advance(std::time::Duration::from_secs(60)).await;
+        bar.expire();
+        assert_eq!(bar.state["note"].full_text, "kept");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn delete() {
+        let mut bar = Bar::new(ProtectedState::default());
+        bar.update(note("hi", Some(10), false)).await.unwrap();
+        bar.update(note("", None, false).with_delete(true))
+            .await
+            .unwrap();
+
+        assert!(!bar.state.contains_key("note"));
+        assert!(!bar.collections.contains_key("note"));
+        assert!(bar.expiry.is_empty());
+    }
+}
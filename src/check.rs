@@ -0,0 +1,300 @@
+use crate::{
+    collectors::{Registry, GRAPH_PLACEHOLDERS},
+    config::{Config, ConfigItem},
+    formatter::Template,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub block: String,
+    // the occurrence of the block name, for blocks with duplicate names
+    pub occurrence: usize,
+    pub field: Option<&'static str>,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(
+        item: &ConfigItem,
+        occurrence: usize,
+        field: Option<&'static str>,
+        message: String,
+    ) -> Self {
+        Self {
+            block: item.name.clone(),
+            occurrence,
+            field,
+            message,
+        }
+    }
+
+    // finds the line and column of the problem in the configuration source, counting from 1
+    pub fn locate(&self, source: &str) -> Option<(usize, usize)> {
+        let lines = source.lines().collect::<Vec<&str>>();
+
+        let (start, column) = lines
+            .iter()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                key(line, "name")
+                    .filter(|(_, value)| {
+                        value.trim_matches(|c| c == '"' || c == '\'') == self.block
+                    })
+                    .map(|(column, _)| (i, column))
+            })
+            .nth(self.occurrence)?;
+
+        if let Some(field) = self.field {
+            for (i, line) in lines.iter().enumerate().skip(start + 1) {
+                if key(line, "name").is_some() {
+                    break;
+                }
+
+                if let Some((column, _)) = key(line, field) {
+                    return Some((i + 1, column));
+                }
+            }
+        }
+
+        Some((start + 1, column))
+    }
+}
+
+// returns the column and value of a `key: value` line, ignoring any list marker
+fn key<'a>(line: &'a str, key: &str) -> Option<(usize, &'a str)> {
+    let trimmed = line.trim_start_matches(|c: char| c == '-' || c.is_whitespace());
+    trimmed
+        .strip_prefix(key)
+        .and_then(|x| x.strip_prefix(':'))
+        .map(|value| (line.len() - trimmed.len() + 1, value.trim()))
+}
+
+fn valid_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|x| (x.len() == 6 || x.len() == 8) && x.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+// a trailing `*` in the placeholder matches any number
+fn placeholder_matches(placeholder: &str, field: &str) -> bool {
+    match placeholder.strip_suffix('*') {
+        Some(prefix) => field
+            .strip_prefix(prefix)
+            .is_some_and(|x| !x.is_empty() && x.chars().all(|c| c.is_ascii_digit())),
+        None => placeholder == field,
+    }
+}
+
+fn check_item(item: &ConfigItem, occurrence: usize, registry: &Registry) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut problem =
+        |field, message| problems.push(Problem::new(item, occurrence, field, message));
+
+    if let Err(e) = item.validate(registry) {
+        problem(None, e.to_string());
+    }
+
+    // options are kept for the collector rather than rejected, so misspelled ones are found here
+    if let Ok(collector) = item.collector(registry) {
+        for key in item.options.keys() {
+            let key = key
+                .as_str()
+                .map_or_else(|| format!("{:?}", key), ToString::to_string);
+            if !collector.options().contains(&key.as_str()) {
+                problem(
+                    None,
+                    format!("unknown option '{}' for type '{}'", key, item.typ),
+                );
+            }
+        }
+    }
+
+    if let Some(urgency) = item.urgency {
+        if urgency.0 > urgency.1 || urgency.1 > urgency.2 {
+            problem(
+                Some("urgency"),
+                format!(
+                    "urgency thresholds [{}, {}, {}] must be ascending",
+                    urgency.0, urgency.1, urgency.2
+                ),
+            );
+        }
+
+        if item.urgency_colors.is_none() {
+            problem(
+                Some("urgency"),
+                "urgency is set without urgency_colors".to_string(),
+            );
+        }
+    } else if item.urgency_colors.is_some() {
+        problem(
+            Some("urgency_colors"),
+            "urgency_colors is set without urgency".to_string(),
+        );
+    }
+
+    if let Some(colors) = &item.urgency_colors {
+        for color in [&colors.0, &colors.1, &colors.2] {
+            if !valid_color(color) {
+                problem(
+                    Some("urgency_colors"),
+                    format!("'{}' is not a color in #rrggbb form", color),
+                );
+            }
+        }
+    }
+
+    if let Some(color) = &item.error_color {
+        if !valid_color(color) {
+            problem(
+                Some("error_color"),
+                format!("'{}' is not a color in #rrggbb form", color),
+            );
+        }
+    }
+
+    if matches!(item.typ.as_str(), "disk" | "hdd") {
+        if let Some(value) = item.value.as_deref() {
+            if !std::path::Path::new(value).is_dir() {
+                problem(
+                    Some("value"),
+                    format!("mount point '{}' does not exist", value),
+                );
+            }
+        }
+    }
+
+    if let Some(placeholders) = item.collector(registry).ok().and_then(|x| x.placeholders()) {
+        for format in item.format.iter().chain(item.formats().iter()) {
+            for field in Template::parse(format)
+                .map(|x| x.fields())
+                .unwrap_or_default()
+            {
+                if !placeholders.iter().any(|x| placeholder_matches(x, &field))
+                    && !GRAPH_PLACEHOLDERS.contains(&field.as_str())
+                {
+                    problem(
+                        Some("format"),
+                        format!(
+                            "unknown placeholder '{}' for type '{}'; available are: {}",
+                            field,
+                            item.typ,
+                            placeholders.join(", ")
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    problems
+}
+
+// Reports every problem with the configuration, instead of stopping at the first like loading does.
+pub fn check(config: &Config, registry: &Registry) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut seen: Vec<String> = Vec::new();
+
+    for item in config.pages().iter().flat_map(|page| page.items()) {
+        let occurrence = seen.iter().filter(|x| **x == item.name).count();
+        if occurrence > 0 {
+            problems.push(Problem::new(
+                &item,
+                occurrence,
+                None,
+                format!("block name '{}' is used more than once", item.name),
+            ));
+        }
+
+        problems.append(&mut check_item(&item, occurrence, registry));
+        seen.push(item.name.clone());
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+pages:
+- - name: cpu
+    type: cpu
+    format: \"{usage}\"
+  - name: \"load\"
+    type: load
+- - name: 'cpu'
+    type: cpu
+    color: red
+";
+
+    fn problem(block: &str, occurrence: usize, field: Option<&'static str>) -> Problem {
+        Problem {
+            block: block.to_string(),
+            occurrence,
+            field,
+            message: String::new(),
+        }
+    }
+
+    fn registry() -> Registry {
+        let (_, commands) = tokio::sync::mpsc::unbounded_channel();
+        Registry::new(std::sync::Arc::new(tokio::sync::Mutex::new(commands)))
+    }
+
+    #[test]
+    fn unknown_options() {
+        let item = |yaml| serde_yaml::from_str::<ConfigItem>(yaml).unwrap();
+        let messages = |yaml| {
+            check_item(&item(yaml), 0, &registry())
+                .into_iter()
+                .map(|x| x.message)
+                .collect::<Vec<String>>()
+        };
+
+        assert!(messages("name: d\ntype: disk\nmounts: [/]\nfstypes: [ext4]").is_empty());
+        assert_eq!(
+            messages("name: d\ntype: disk\nmount: [/]"),
+            vec!["unknown option 'mount' for type 'disk'"]
+        );
+        assert_eq!(
+            messages("name: c\ntype: cpu\nshell: true"),
+            vec!["unknown option 'shell' for type 'cpu'"]
+        );
+    }
+
+    #[test]
+    fn block() {
+        assert_eq!(problem("cpu", 0, None).locate(SOURCE), Some((2, 5)));
+        assert_eq!(problem("load", 0, None).locate(SOURCE), Some((5, 5)));
+        assert_eq!(problem("missing", 0, None).locate(SOURCE), None);
+    }
+
+    #[test]
+    fn duplicate_names() {
+        assert_eq!(problem("cpu", 1, None).locate(SOURCE), Some((7, 5)));
+        assert_eq!(
+            problem("cpu", 1, Some("color")).locate(SOURCE),
+            Some((9, 5))
+        );
+        assert_eq!(problem("cpu", 2, None).locate(SOURCE), None);
+    }
+
+    #[test]
+    fn field() {
+        assert_eq!(
+            problem("cpu", 0, Some("format")).locate(SOURCE),
+            Some((4, 5))
+        );
+        // the field of another block is not the one with the problem
+        assert_eq!(
+            problem("cpu", 0, Some("color")).locate(SOURCE),
+            Some((2, 5))
+        );
+        assert_eq!(
+            problem("load", 0, Some("format")).locate(SOURCE),
+            Some((5, 5))
+        );
+    }
+}
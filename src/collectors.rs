@@ -1,411 +1,872 @@
 use crate::{
     bar::Block,
     config::{CommandItem, ConfigItem},
-    formatter::{Format, Rules},
+    formatter::{Format, Graph, Rules, Template, Value},
 };
 use anyhow::{anyhow, Result};
-use pretty_bytes::converter::convert;
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+};
 use tokio::sync::{
     mpsc::{UnboundedReceiver, UnboundedSender},
     Mutex,
 };
 
+// Declares a collector that reads its files below a root directory, normally one in /sys or
+// /proc, so tests can point it at a fake tree instead.
+macro_rules! rooted_collector {
+    ($name:ident, $root:expr) => {
+        #[derive(Debug, Clone)]
+        pub struct $name {
+            root: std::path::PathBuf,
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new($root.into())
+            }
+        }
+
+        impl $name {
+            pub fn new(root: std::path::PathBuf) -> Self {
+                Self { root }
+            }
+        }
+    };
+}
+
+pub mod battery;
+pub mod command;
+pub mod cpu;
+pub mod cpufreq;
+pub mod disk;
+pub mod diskio;
+pub mod network;
+pub mod pressure;
+pub mod temperature;
+
+pub type CollectorFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+// the outcome of a collector run, by the name of the block it ran for
+pub type CollectorResult = (String, Result<()>);
+
+// shown in place of a block whose collector failed, unless the block sets error_format and
+// error_color
+pub const ERROR_FORMAT: &str = "{name}: {error}";
+pub const ERROR_COLOR: &str = "#FF6666";
+
+// blocks written with a ttl and stale set are shown in this color once the ttl has passed
+pub const STALE_COLOR: &str = "#888888";
+
+// shown between the segments of a block, e.g. the mounts of a disk block
+pub const SEGMENT_SEPARATOR: &str = " | ";
+
+// A Collector gathers data for a block and sends the result down the channel as a Collection.
+// Register them by their `type:` name in a Registry to make them usable from the configuration.
+pub trait Collector: Send + Sync {
+    fn validate(&self, item: &ConfigItem) -> Result<()> {
+        validate_format(item)
+    }
+
+    // the placeholders available to formats, if the collector uses them; a trailing `*` stands
+    // for a number, e.g. `core*` for `core0`, `core1` and so on
+    fn placeholders(&self) -> Option<&'static [&'static str]> {
+        None
+    }
+
+    // the options a block of this type may set besides the keys common to all blocks, which the
+    // collector reads with ConfigItem::option and checks in validate
+    fn options(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    // whether updates are aligned to multiples of the update interval on the clock, e.g. to the
+    // start of every minute
+    fn aligned(&self) -> bool {
+        false
+    }
+
+    // whether the collector can keep running and update the block as it goes, for blocks that
+    // set persist; it is then not stopped by the timeout
+    fn persists(&self) -> bool {
+        false
+    }
+
+    // whether the value may be a list, kept quoted as one string, e.g. the arguments of a command
+    fn arguments(&self) -> bool {
+        false
+    }
+
+    fn collect(&self, s: UnboundedSender<Collection>, item: ConfigItem) -> CollectorFuture;
+}
+
+impl<F, Fut> Collector for F
+where
+    F: Fn(UnboundedSender<Collection>, ConfigItem) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    fn collect(&self, s: UnboundedSender<Collection>, item: ConfigItem) -> CollectorFuture {
+        Box::pin(self(s, item))
+    }
+}
+
+// Attaches the placeholders to a collector, for collectors written as functions.
+pub struct WithPlaceholders<C>(pub C, pub &'static [&'static str]);
+
+impl<C: Collector> Collector for WithPlaceholders<C> {
+    fn validate(&self, item: &ConfigItem) -> Result<()> {
+        self.0.validate(item)
+    }
+
+    fn placeholders(&self) -> Option<&'static [&'static str]> {
+        Some(self.1)
+    }
+
+    fn options(&self) -> &'static [&'static str] {
+        self.0.options()
+    }
+
+    fn aligned(&self) -> bool {
+        self.0.aligned()
+    }
+
+    fn persists(&self) -> bool {
+        self.0.persists()
+    }
+
+    fn arguments(&self) -> bool {
+        self.0.arguments()
+    }
+
+    fn collect(&self, s: UnboundedSender<Collection>, item: ConfigItem) -> CollectorFuture {
+        self.0.collect(s, item)
+    }
+}
+
+// available to every block with a format
+pub const GRAPH_PLACEHOLDERS: &[&str] = &["spark", "braille", "bar"];
+
+#[derive(Clone, Default)]
+pub struct Registry(BTreeMap<String, Arc<dyn Collector>>);
+
+impl Registry {
+    pub fn new(commands: Arc<Mutex<UnboundedReceiver<CommandItem>>>) -> Self {
+        let mut registry = Self::default();
+        registry.register("static", StaticCollector);
+        registry.register("dynamic", move |s, item| {
+            collect_dynamic(s, item, commands.clone())
+        });
+        registry.register("cpu", cpu::CpuCollector::default());
+        registry.register("cpufreq", cpufreq::CpufreqCollector::default());
+        registry.register("pressure", pressure::PressureCollector::default());
+        registry.alias("psi", "pressure");
+        registry.register("disk", disk::DiskCollector);
+        registry.alias("hdd", "disk");
+        registry.register("diskio", diskio::DiskioCollector::default());
+        registry.register(
+            "memory",
+            WithPlaceholders(
+                collect_memory,
+                &[
+                    "total",
+                    "usage",
+                    "swap_total",
+                    "swap_usage",
+                    "pct",
+                    "pct_swap",
+                ],
+            ),
+        );
+        registry.alias("ram", "memory");
+        registry.register("load", WithPlaceholders(collect_load, &["1", "5", "15"]));
+        registry.alias("load_average", "load");
+        registry.register("time", TimeCollector);
+        registry.alias("clock", "time");
+        registry.register("music", MusicCollector::default());
+        registry.register("command", command::CommandCollector);
+        registry.register("network", network::NetworkCollector::default());
+        registry.alias("net", "network");
+        registry.register("battery", battery::BatteryCollector::default());
+        registry.register("temperature", temperature::TemperatureCollector::default());
+        registry.alias("temp", "temperature");
+        registry
+    }
+
+    pub fn register(&mut self, typ: &str, collector: impl Collector + 'static) {
+        self.0.insert(typ.to_string(), Arc::new(collector));
+    }
+
+    pub fn alias(&mut self, alias: &str, typ: &str) {
+        if let Some(collector) = self.0.get(typ).cloned() {
+            self.0.insert(alias.to_string(), collector);
+        }
+    }
+
+    pub fn get(&self, typ: &str) -> Option<Arc<dyn Collector>> {
+        self.0.get(typ).cloned()
+    }
+
+    pub fn types(&self) -> Vec<String> {
+        self.0.keys().cloned().collect()
+    }
+}
+
+impl std::fmt::Debug for Registry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Registry").field(&self.types()).finish()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Collection {
     name: String,
-    value: Option<String>,
-    format: Option<String>,
-    collection_type: CollectionType,
-    item: crate::config::ConfigItem,
+    format: String,
+    rules: Rules<'static>,
+    percent: u64,
+    urgent: bool,
+    error: bool,
+    // the format is text to show as it is, e.g. the output of a command, not a template. Formats
+    // cycled through by clicking are still templates.
+    verbatim: bool,
+    // fields set by commands, which take precedence over those of the block, e.g. a color
+    // over the urgency color
+    overrides: Block,
+    // how long the block is shown for, and whether it is then greyed out instead of removed
+    ttl: Option<std::time::Duration>,
+    stale: bool,
+    // removes the block from the bar instead of showing it
+    delete: bool,
+    // parts of the block each rendered with the format, the rules being those shown when
+    // the block is collapsed
+    segments: Vec<Rules<'static>>,
+    item: ConfigItem,
 }
 
 impl Collection {
+    // format is the fallback used when the block does not configure its own
+    pub fn new(item: ConfigItem, format: &str, rules: Rules<'static>, percent: u64) -> Self {
+        Self {
+            name: item.name.clone(),
+            format: item.format.clone().unwrap_or(format.to_string()),
+            rules,
+            percent,
+            urgent: false,
+            error: false,
+            verbatim: false,
+            overrides: Block::default(),
+            ttl: None,
+            stale: false,
+            delete: false,
+            segments: Vec::new(),
+            item,
+        }
+    }
+
+    // text is displayed as-is, without applying any format
+    pub fn text(item: ConfigItem, text: String, percent: u64) -> Self {
+        Self {
+            name: item.name.clone(),
+            format: text,
+            rules: Rules::default(),
+            percent,
+            urgent: false,
+            error: false,
+            verbatim: true,
+            overrides: Block::default(),
+            ttl: None,
+            stale: false,
+            delete: false,
+            segments: Vec::new(),
+            item,
+        }
+    }
+
+    // replaces the block with its error indicator until the collector succeeds again
+    pub fn error(item: ConfigItem, error: &str) -> Self {
+        Self {
+            name: item.name.clone(),
+            format: item
+                .error_format
+                .clone()
+                .unwrap_or(ERROR_FORMAT.to_string()),
+            rules: vec![
+                ("name".into(), item.name.clone().into()),
+                ("error".into(), error.replace('\n', " ").into()),
+            ],
+            percent: 0,
+            urgent: true,
+            error: true,
+            verbatim: false,
+            overrides: Block::default(),
+            ttl: None,
+            stale: false,
+            delete: false,
+            segments: Vec::new(),
+            item,
+        }
+    }
+
+    // removes the block's error indicator, if it still shows one, for a collector that succeeds
+    // without showing anything, e.g. music with no player running
+    pub fn cleared(item: ConfigItem) -> Self {
+        Self::error(item, "").with_delete(true)
+    }
+
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn with_segments(mut self, segments: Vec<Rules<'static>>) -> Self {
+        self.segments = segments;
+        self
+    }
+
+    pub fn with_urgent(mut self, urgent: bool) -> Self {
+        self.urgent = urgent;
+        self
+    }
+
+    pub fn with_overrides(mut self, overrides: Block) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Option<std::time::Duration>, stale: bool) -> Self {
+        self.ttl = ttl;
+        self.stale = stale;
+        self
+    }
+
+    pub fn with_delete(mut self, delete: bool) -> Self {
+        self.delete = delete;
+        self
+    }
+
     pub fn name(&self) -> String {
         self.name.clone()
     }
 
-    pub fn collection_type(&self) -> CollectionType {
-        self.collection_type.clone()
+    pub fn percent(&self) -> u64 {
+        self.percent
     }
 
-    fn get_formatter(&self) -> Format {
-        let pair = match &self.collection_type {
-            CollectionType::Static => (self.value.clone().unwrap(), Rules::default()),
-            CollectionType::Command(_) | CollectionType::Dynamic(_) => (
-                if let Some(icon) = &self.item.icon {
-                    format!("{}: {}", icon, self.value.clone().unwrap())
-                } else {
-                    self.value.clone().unwrap()
-                },
-                Rules::default(),
-            ),
-            CollectionType::Time(t) => (
-                t.format(&self.format.clone().unwrap_or("%m/%d %H:%M".to_string()))
-                    .to_string(),
-                Rules::default(),
-            ),
-            CollectionType::Load(one, five, fifteen) => (
-                self.format.clone().unwrap_or("%1, %5, %15".to_string()),
-                vec![
-                    ("%1", one.to_string()),
-                    ("%5", five.to_string()),
-                    ("%15", fifteen.to_string()),
-                ],
-            ),
-            CollectionType::CPU { count, usage } => (
-                self.format
-                    .clone()
-                    .unwrap_or("cpus: %count, usage: %usage".to_string()),
-                vec![
-                    ("%count", count.to_string()),
-                    ("%usage", format!("{:.2}", usage)),
-                ],
-            ),
-            CollectionType::Memory {
-                total,
-                usage,
-                swap_total,
-                swap_usage,
-            } => (
-                self.format
-                    .clone()
-                    .unwrap_or("total: %total, usage: %usage".to_string()),
-                vec![
-                    ("%total", convert(*total as f64)),
-                    ("%usage", convert(*usage as f64)),
-                    ("%swap_total", convert(*swap_total as f64)),
-                    ("%swap_usage", convert(*swap_usage as f64)),
-                    (
-                        "%pct",
-                        format!("{:.1}", (*usage as f64 / *total as f64) * 100.0),
-                    ),
-                    (
-                        "%pct_swap",
-                        format!("{:.1}", (*swap_usage as f64 / *swap_total as f64) * 100.0),
-                    ),
-                ],
-            ),
-            CollectionType::Disk { total, usage } => (
-                self.format
-                    .clone()
-                    .unwrap_or("total: %total, usage: %usage".to_string()),
-                vec![
-                    ("%total", convert(*total as f64)),
-                    ("%usage", convert(*usage as f64)),
-                    (
-                        "%pct",
-                        format!("{:.1}", (*usage as f64 / *total as f64) * 100.0),
-                    ),
-                ],
-            ),
-            CollectionType::Music {
-                artist,
-                title,
-                pct_played,
-                time_played,
-            } => (
-                self.format
-                    .clone()
-                    .unwrap_or("music: %artist - %title".to_string()),
-                vec![
-                    ("%artist", artist.clone()),
-                    ("%title", title.clone()),
-                    ("%pct_played", pct_played.to_string()),
-                    (
-                        "%time",
-                        format!(
-                            "{}:{:0>2}",
-                            chrono::Duration::seconds(*time_played as i64).num_minutes(),
-                            chrono::Duration::seconds((time_played % 60) as i64).num_seconds()
-                        ),
-                    ),
-                ],
-            ),
+    pub fn ttl(&self) -> Option<std::time::Duration> {
+        self.ttl
+    }
+
+    pub fn stale(&self) -> bool {
+        self.stale
+    }
+
+    pub fn delete(&self) -> bool {
+        self.delete
+    }
+
+    pub fn failed(&self) -> bool {
+        self.error
+    }
+
+    fn get_formatter(
+        &self,
+        rules: &Rules<'static>,
+        history: Vec<u64>,
+        format: usize,
+    ) -> Format<'_> {
+        let format = match format {
+            0 => self.format.clone(),
+            x => self
+                .item
+                .formats()
+                .get(x - 1)
+                .cloned()
+                .unwrap_or(self.format.clone()),
         };
-        Format::new(pair.0, pair.1)
+
+        let mut rules = rules.clone();
+        let last = history.last().copied().unwrap_or_default();
+        rules.push(("spark".into(), Value::Graph(Graph::Spark, history.clone())));
+        rules.push(("braille".into(), Value::Graph(Graph::Braille, history)));
+        rules.push(("bar".into(), Value::Graph(Graph::Bar, vec![last])));
+        Format::new(format, rules)
     }
 
+    // renders the block, recording its percent in the history of its graphs
     pub async fn to_block(&self, state: crate::state::ProtectedState) -> Result<Block> {
-        let mut block = Block::default();
+        self.render(state, true).await
+    }
 
-        let pct = match &self.collection_type {
-            CollectionType::Static => 0,
-            CollectionType::Command(command) | CollectionType::Dynamic(command) => {
-                command.percent.unwrap_or(0)
-            }
-            CollectionType::CPU { count: _, usage } => usage.floor() as u64,
-            CollectionType::Disk { total, usage } => {
-                ((*usage as f64 / *total as f64) * 100.0).floor() as u64
-            }
-            CollectionType::Load(one, ..) => {
-                ((one / num_cpus::get() as f64) * 100.0).floor() as u64
-            }
-            CollectionType::Memory { total, usage, .. } => {
-                ((*usage as f64 / *total as f64) * 100.0).floor() as u64
-            }
-            CollectionType::Time(..) => 0,
-            CollectionType::Music {
-                artist: _,
-                title: _,
-                pct_played,
-                time_played: _,
-            } => *pct_played as u64,
-        };
+    // renders the block again with the history it has, e.g. when a click changes how it is shown
+    pub async fn redraw(&self, state: crate::state::ProtectedState) -> Result<Block> {
+        self.render(state, false).await
+    }
 
-        let urgency = if let Some(colors) = &self.item.urgency_colors {
-            if let Some(urgency) = self.item.urgency {
-                if pct > urgency.0.into() {
-                    if pct > urgency.1.into() {
-                        if pct > urgency.2.into() {
-                            Some(colors.2.clone())
-                        } else {
-                            Some(colors.1.clone())
-                        }
-                    } else {
-                        Some(colors.0.clone())
-                    }
+    async fn render(&self, state: crate::state::ProtectedState, record: bool) -> Result<Block> {
+        if self.error {
+            return Ok(Block {
+                name: Some(self.name()),
+                full_text: Format::new(self.format.clone(), self.rules.clone()).format(),
+                color: Some(
+                    self.item
+                        .error_color
+                        .clone()
+                        .unwrap_or(ERROR_COLOR.to_string()),
+                ),
+                urgent: Some(true),
+                ..Default::default()
+            });
+        }
+
+        let mut block = Block::default();
+        let pct = self.percent;
+        let (history, format) = {
+            let mut lock = state.lock().await;
+            (
+                if record {
+                    lock.record(&self.name, pct)
                 } else {
-                    None
-                }
-            } else {
-                None
-            }
-        } else {
-            None
+                    lock.history(&self.name)
+                },
+                lock.formats.get(&self.name).copied().unwrap_or_default(),
+            )
         };
 
-        if let Some(urgency) = urgency {
+        if let Some(urgency) = self.item.urgency_color(pct) {
             block.color = Some(urgency);
         }
 
         block.name = Some(self.name());
+        block.markup = self.item.markup.clone();
+
+        if self.urgent {
+            block.urgent = Some(true);
+        }
 
-        if let Some(icon) = &self.item.icon {
-            if state.lock().await.opened.contains(&self.name()) {
-                block.full_text = self.get_formatter().format();
+        let render = |rules: &Rules<'static>| {
+            if self.verbatim && format == 0 {
+                self.format.clone()
             } else {
-                block.full_text = icon.clone()
+                self.get_formatter(rules, history.clone(), format).format()
             }
-        } else {
-            block.full_text = self.get_formatter().format();
-        }
+        };
+        let opened = state.lock().await.opened.contains(&self.name());
+
+        block.full_text = match &self.item.icon {
+            // a block with segments shows the one its rules are for next to the icon
+            Some(icon) if !opened && !self.segments.is_empty() => {
+                format!("{} {}", icon, render(&self.rules))
+            }
+            Some(icon) if !opened => icon.clone(),
+            _ if !self.segments.is_empty() => self
+                .segments
+                .iter()
+                .map(render)
+                .collect::<Vec<String>>()
+                .join(SEGMENT_SEPARATOR),
+            _ => render(&self.rules),
+        };
 
+        block.merge(&self.overrides);
         Ok(block)
     }
 }
 
-// every edit to this must mirror a ModuleType
-#[derive(Debug, Clone)]
-pub enum CollectionType {
-    Static,
-    Dynamic(CommandItem),
-    CPU {
-        count: usize,
-        usage: f64,
-    },
-    Disk {
-        total: usize,
-        usage: usize,
-    },
-    Memory {
-        total: usize,
-        usage: usize,
-        swap_total: usize,
-        swap_usage: usize,
-    },
-    Load(f64, f64, f64),
-    Time(chrono::DateTime<chrono::Local>),
-    Music {
-        artist: String,
-        title: String,
-        pct_played: usize,
-        time_played: usize,
-    },
-    Command(CommandItem),
+pub fn validate_format(item: &ConfigItem) -> Result<()> {
+    for format in item.format.iter().chain(item.formats().iter()) {
+        Template::parse(format).map_err(|e| anyhow!("Block '{}': {}", item.name, e))?;
+    }
+
+    Ok(())
 }
 
-pub async fn collect_static(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
-    let clone = item.clone();
-    Ok(s.send(Collection {
-        name: item.name,
-        collection_type: CollectionType::Static,
-        value: item.value,
-        format: item.format,
-        item: clone,
-    })?)
+// the trimmed contents of a file in a directory, for the one value files of /sys
+fn read(path: &std::path::Path, file: &str) -> Option<String> {
+    std::fs::read_to_string(path.join(file))
+        .ok()
+        .map(|x| x.trim().to_string())
 }
 
-pub async fn collect_time(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
-    let clone = item.clone();
-    Ok(s.send(Collection {
-        name: item.name,
-        collection_type: CollectionType::Time(chrono::Local::now()),
-        value: item.value,
-        format: item.format,
-        item: clone,
-    })?)
+// Counters read at a point in time. The previous sample of each block is kept so rates can be
+// computed between runs.
+#[derive(Debug, Clone, Copy)]
+struct Sample<const N: usize> {
+    at: std::time::Instant,
+    counters: [u64; N],
 }
 
-pub async fn collect_load(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
-    let avg = mprober_lib::load_average::get_load_average()?;
-    let clone = item.clone();
-
-    Ok(s.send(Collection {
-        name: item.name,
-        collection_type: CollectionType::Load(avg.one, avg.five, avg.fifteen),
-        value: item.value,
-        format: item.format,
-        item: clone,
-    })?)
+impl<const N: usize> Sample<N> {
+    fn new(counters: [u64; N]) -> Self {
+        Self {
+            at: std::time::Instant::now(),
+            counters,
+        }
+    }
+
+    // how much each counter grew per second since the last sample, which is nothing on the
+    // first run or if the counter was reset
+    fn rates(&self, last: Option<&Self>) -> [f64; N] {
+        let Some(last) = last.filter(|last| self.at > last.at) else {
+            return [0.0; N];
+        };
+
+        let secs = (self.at - last.at).as_secs_f64();
+        std::array::from_fn(|i| self.counters[i].saturating_sub(last.counters[i]) as f64 / secs)
+    }
 }
 
-pub async fn collect_cpu(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
-    let avg = mprober_lib::cpu::get_all_cpu_utilization_in_percentage(
-        false,
-        std::time::Duration::from_millis(100),
-    )?;
-
-    let count = avg.len();
-    let avg = avg.iter().fold(0.0, |acc, item| item + acc) / count as f64;
-    let clone = item.clone();
-
-    Ok(s.send(Collection {
-        name: item.name,
-        collection_type: CollectionType::CPU {
-            count,
-            usage: avg * 100.0,
-        },
-        value: item.value,
-        format: item.format,
-        item: clone,
-    })?)
+type Samples<const N: usize> = Arc<std::sync::Mutex<std::collections::HashMap<String, Sample<N>>>>;
+
+fn pct(usage: usize, total: usize) -> f64 {
+    (usage as f64 / total as f64) * 100.0
 }
 
-pub async fn collect_memory(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
-    let mem = mprober_lib::memory::free()?;
-    let clone = item.clone();
-
-    Ok(s.send(Collection {
-        name: item.name,
-        collection_type: CollectionType::Memory {
-            total: mem.mem.total,
-            usage: mem.mem.used,
-            swap_total: mem.swap.total,
-            swap_usage: mem.swap.used,
-        },
-        value: item.value,
-        format: item.format,
-        item: clone,
-    })?)
+struct StaticCollector;
+
+impl Collector for StaticCollector {
+    fn validate(&self, item: &ConfigItem) -> Result<()> {
+        if item.value.is_none() {
+            return Err(anyhow!("Static block '{}' must have a value", item.name));
+        }
+
+        Ok(())
+    }
+
+    fn collect(&self, s: UnboundedSender<Collection>, item: ConfigItem) -> CollectorFuture {
+        Box::pin(collect_static(s, item))
+    }
 }
 
-pub async fn collect_disk(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
-    let clone = item.clone();
-    if let Some(value) = item.value {
-        let vols = mprober_lib::volume::get_volumes()?;
-        let mut target: Option<mprober_lib::volume::Volume> = None;
+struct TimeCollector;
 
-        for vol in vols {
-            if vol.points.contains(&value) {
-                target = Some(vol);
-                break;
-            }
+impl Collector for TimeCollector {
+    // time blocks are formatted with strftime instead of templates
+    fn validate(&self, item: &ConfigItem) -> Result<()> {
+        if !item.formats().is_empty() {
+            return Err(anyhow!(
+                "Time block '{}' cannot cycle formats, as it is formatted with strftime",
+                item.name
+            ));
         }
 
-        if let Some(target) = target {
-            Ok(s.send(Collection {
-                name: item.name,
-                collection_type: CollectionType::Disk {
-                    total: target.size as usize,
-                    usage: target.used as usize,
-                },
-                value: Some(value),
-                format: item.format,
-                item: clone,
-            })?)
-        } else {
-            Err(anyhow!("Volume could not be found"))
+        if let Some(format) = &item.format {
+            if chrono::format::StrftimeItems::new(format).any(|x| x == chrono::format::Item::Error)
+            {
+                return Err(anyhow!(
+                    "Block '{}': invalid time format '{}'",
+                    item.name,
+                    format
+                ));
+            }
         }
-    } else {
-        Err(anyhow!(
-            "Value must be provided and must point at a mount point"
-        ))
+
+        Ok(())
+    }
+
+    fn aligned(&self) -> bool {
+        true
+    }
+
+    fn collect(&self, s: UnboundedSender<Collection>, item: ConfigItem) -> CollectorFuture {
+        Box::pin(collect_time(s, item))
+    }
+}
+
+pub async fn collect_static(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
+    if let Some(value) = item.value.as_deref().map(ToString::to_string) {
+        s.send(Collection::text(item, value, 0))?;
     }
+
+    Ok(())
+}
+
+pub async fn collect_time(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
+    let text = chrono::Local::now()
+        .format(&item.format.clone().unwrap_or("%m/%d %H:%M".to_string()))
+        .to_string();
+    Ok(s.send(Collection::text(item, text, 0))?)
+}
+
+pub async fn collect_load(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
+    let avg = mprober_lib::load_average::get_load_average()?;
+
+    Ok(s.send(Collection::new(
+        item,
+        "{1}, {5}, {15}",
+        vec![
+            ("1".into(), Value::Float(avg.one, None)),
+            ("5".into(), Value::Float(avg.five, None)),
+            ("15".into(), Value::Float(avg.fifteen, None)),
+        ],
+        ((avg.one / num_cpus::get() as f64) * 100.0).floor() as u64,
+    ))?)
+}
+
+pub async fn collect_memory(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
+    let mem = mprober_lib::memory::free()?;
+
+    Ok(s.send(Collection::new(
+        item,
+        "total: {total}, usage: {usage}",
+        vec![
+            ("total".into(), Value::Bytes(mem.mem.total as f64)),
+            ("usage".into(), Value::Bytes(mem.mem.used as f64)),
+            ("swap_total".into(), Value::Bytes(mem.swap.total as f64)),
+            ("swap_usage".into(), Value::Bytes(mem.swap.used as f64)),
+            (
+                "pct".into(),
+                Value::Float(pct(mem.mem.used, mem.mem.total), Some(1)),
+            ),
+            (
+                "pct_swap".into(),
+                Value::Float(pct(mem.swap.used, mem.swap.total), Some(1)),
+            ),
+        ],
+        pct(mem.mem.used, mem.mem.total).floor() as u64,
+    ))?)
 }
 
-pub async fn collect_music(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
+// the D-Bus calls block, so they run on their own thread where a timeout cannot stall the runtime
+fn music_collection(item: ConfigItem) -> Result<Option<Collection>> {
     if let Ok(player) = mpris::PlayerFinder::new()?.find_active() {
         if player.is_running() {
             if let Ok(meta) = player.get_metadata() {
-                let clone = item.clone();
                 let position = player.get_position().unwrap_or_default();
-                s.send(Collection {
-                    name: item.name,
-                    collection_type: CollectionType::Music {
-                        artist: meta.artists().map_or_else(String::new, |x| x.join(", ")),
-                        title: meta.title().unwrap_or_default().to_string(),
-                        pct_played: meta.length().map_or_else(
-                            || 100,
-                            |length| (position.as_secs() / length.as_secs()) as usize,
+                let pct_played = meta.length().map_or_else(
+                    || 100,
+                    |length| (position.as_secs() / length.as_secs()) as usize,
+                );
+                let time_played = position.as_secs();
+
+                return Ok(Some(Collection::new(
+                    item,
+                    "music: {?artist:{artist} - }{title}",
+                    vec![
+                        (
+                            "artist".into(),
+                            meta.artists()
+                                .map_or_else(String::new, |x| x.join(", "))
+                                .into(),
                         ),
-                        time_played: position.as_secs() as usize,
-                    },
-                    format: item.format,
-                    item: clone,
-                    value: None,
-                })?;
+                        ("title".into(), meta.title().unwrap_or_default().into()),
+                        ("pct_played".into(), pct_played.into()),
+                        (
+                            "time".into(),
+                            format!(
+                                "{}:{:0>2}",
+                                chrono::Duration::seconds(time_played as i64).num_minutes(),
+                                chrono::Duration::seconds((time_played % 60) as i64).num_seconds()
+                            )
+                            .into(),
+                        ),
+                    ],
+                    pct_played as u64,
+                )));
             }
         }
     }
 
-    Ok(())
+    Ok(None)
+}
+
+// The names of the blocks with D-Bus calls in flight. A blocking task cannot be stopped by the
+// timeout, so a block is not collected again until its last call has returned.
+#[derive(Default)]
+struct MusicCollector(Arc<std::sync::Mutex<BTreeSet<String>>>);
+
+impl Collector for MusicCollector {
+    fn placeholders(&self) -> Option<&'static [&'static str]> {
+        Some(&["artist", "title", "pct_played", "time"])
+    }
+
+    fn collect(&self, s: UnboundedSender<Collection>, item: ConfigItem) -> CollectorFuture {
+        Box::pin(collect_music(s, item, self.0.clone()))
+    }
+}
+
+// removes the block from those in flight when the blocking task is done with it
+struct InFlight(Arc<std::sync::Mutex<BTreeSet<String>>>, String);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().remove(&self.1);
+    }
 }
 
-pub async fn collect_command(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
-    let clone = item.clone();
-
-    if let Some(value) = item.value {
-        let parts = value
-            .clone()
-            .split(" ")
-            .map(ToString::to_string)
-            .collect::<Vec<String>>();
-        let command: CommandItem = serde_json::from_slice(
-            &tokio::process::Command::new(parts[0].clone())
-                .args(&parts[1..parts.len()])
-                .output()
-                .await
-                .map(|x| x.stdout)?,
-        )?;
-
-        let c = command.clone();
-        s.send(Collection {
-            name: command.name,
-            collection_type: CollectionType::Command(c),
-            item: clone,
-            value: Some(command.value),
-            format: None,
-        })?;
+async fn collect_music(
+    s: UnboundedSender<Collection>,
+    item: ConfigItem,
+    in_flight: Arc<std::sync::Mutex<BTreeSet<String>>>,
+) -> Result<()> {
+    if !in_flight.lock().unwrap().insert(item.name.clone()) {
+        return Err(anyhow!("the previous update is still running"));
+    }
+    let guard = InFlight(in_flight, item.name.clone());
+
+    let collection = tokio::task::spawn_blocking(move || {
+        let _guard = guard;
+        music_collection(item)
+    })
+    .await??;
+
+    if let Some(collection) = collection {
+        s.send(collection)?;
     }
 
     Ok(())
 }
 
+// command and dynamic output is shown with the icon, if there is one
+fn command_collection(item: ConfigItem, command: CommandItem) -> Collection {
+    // output written for i3bar has its text in full_text rather than value
+    let value = if command.value.is_empty() {
+        command.block.full_text.clone()
+    } else {
+        command.value
+    };
+
+    let text = if let Some(icon) = &item.icon {
+        format!("{}: {}", icon, value)
+    } else {
+        value
+    };
+
+    Collection::text(item, text, command.percent.unwrap_or(0))
+        .with_name(command.name)
+        .with_overrides(command.block)
+        .with_ttl(
+            command.ttl.and_then(|x| x.duration().to_std().ok()),
+            command.stale.unwrap_or(false),
+        )
+        .with_delete(command.delete.unwrap_or(false))
+}
+
 pub async fn collect_dynamic(
     s: UnboundedSender<Collection>,
     item: ConfigItem,
     commands: Arc<Mutex<UnboundedReceiver<CommandItem>>>,
 ) -> Result<()> {
     while let Ok(command) = commands.lock().await.try_recv() {
-        let c = command.clone();
-        s.send(Collection {
-            name: command.name,
-            collection_type: CollectionType::Dynamic(c),
-            format: None,
-            item: item.clone(),
-            value: Some(command.value),
-        })?;
+        s.send(command_collection(item.clone(), command))?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    // an empty directory of the test's own, standing in for /proc or /sys
+    pub fn fake_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("yaib-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    pub fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    pub fn item(yaml: &str) -> ConfigItem {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    pub async fn collect(collector: &impl Collector, item: ConfigItem) -> Result<Collection> {
+        let (s, mut r) = tokio::sync::mpsc::unbounded_channel();
+        collector.collect(s, item).await?;
+        r.recv()
+            .await
+            .ok_or_else(|| anyhow!("Nothing was collected"))
+    }
+
+    pub fn rule(collection: &Collection, name: &str) -> String {
+        collection
+            .rules
+            .iter()
+            .find(|x| x.0 == name)
+            .map(|x| x.1.to_string())
+            .unwrap_or_else(|| panic!("no rule named '{}'", name))
+    }
+
+    pub async fn render(collection: &Collection) -> Block {
+        collection
+            .to_block(crate::state::ProtectedState::default())
+            .await
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{test::*, *};
+
+    #[test]
+    fn sample_rates() {
+        let at = std::time::Instant::now();
+        let last = Sample {
+            at,
+            counters: [100, 500],
+        };
+        let now = Sample {
+            at: at + std::time::Duration::from_millis(500),
+            counters: [300, 400],
+        };
+
+        // a counter that went backwards was reset, so nothing is known of it
+        assert_eq!(now.rates(Some(&last)), [400.0, 0.0]);
+        assert_eq!(now.rates(None), [0.0, 0.0]);
+        assert_eq!(last.rates(Some(&now)), [0.0, 0.0]);
+    }
+
+    async fn command_text(value: &str) -> String {
+        let command = CommandItem {
+            name: "cmd".to_string(),
+            value: value.to_string(),
+            percent: Some(50),
+            ..Default::default()
+        };
+
+        render(&command_collection(
+            item("name: cmd\ntype: command\nvalue: x"),
+            command,
+        ))
+        .await
+        .full_text
+    }
+
+    #[tokio::test]
+    async fn command_text_is_verbatim() {
+        for value in ["a {b} }", "{\"k\": 1}", "{{", "%name and %1"] {
+            assert_eq!(command_text(value).await, value);
+        }
+    }
+
+    #[tokio::test]
+    async fn full_text_without_value() {
+        let command: CommandItem =
+            serde_json::from_str(r##"{"name":"x","full_text":"hi","color":"#00FF00"}"##).unwrap();
+        let block = render(&command_collection(item("name: x\ntype: dynamic"), command)).await;
+
+        assert_eq!(block.full_text, "hi");
+        assert_eq!(block.color.as_deref(), Some("#00FF00"));
+
+        let command: CommandItem =
+            serde_json::from_str(r#"{"name":"x","value":"shown","full_text":"hidden"}"#).unwrap();
+        let block = render(&command_collection(item("name: x\ntype: dynamic"), command)).await;
+
+        assert_eq!(block.full_text, "shown");
+    }
+
+    #[tokio::test]
+    async fn command_text_has_no_graphs() {
+        for value in ["100%bar", "{spark} %braille"] {
+            assert_eq!(command_text(value).await, value);
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use super::{read, Collection, Collector, CollectorFuture};
+use crate::{config::ConfigItem, formatter::Value};
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc::UnboundedSender;
+
+pub const SYS_CLASS_POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+// Battery charge below this percent is marked urgent if the block has no urgency configured.
+const DEFAULT_CRITICAL: u64 = 5;
+
+// root is the directory holding the power supplies, normally /sys/class/power_supply
+rooted_collector!(BatteryCollector, SYS_CLASS_POWER_SUPPLY);
+
+impl Collector for BatteryCollector {
+    fn placeholders(&self) -> Option<&'static [&'static str]> {
+        Some(&["pct", "status", "remaining", "power", "ac"])
+    }
+
+    fn collect(&self, s: UnboundedSender<Collection>, item: ConfigItem) -> CollectorFuture {
+        Box::pin(collect_battery(s, item, self.root.clone()))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Battery {
+    status: String,
+    capacity: f64,
+    // these are in µWh and µW, the charge and current based attributes are converted
+    now: Option<f64>,
+    full: Option<f64>,
+    power: Option<f64>,
+}
+
+fn read_f64(path: &Path, file: &str) -> Option<f64> {
+    read(path, file).and_then(|x| x.parse::<f64>().ok())
+}
+
+fn supplies(root: &Path, prefix: &str) -> Result<Vec<PathBuf>> {
+    let mut res = Vec::new();
+
+    for entry in std::fs::read_dir(root)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with(prefix) {
+            res.push(entry.path());
+        }
+    }
+
+    res.sort();
+    Ok(res)
+}
+
+impl Battery {
+    fn read(path: &Path) -> Option<Self> {
+        let voltage = read_f64(path, "voltage_now").map(|x| x / 1000000.0);
+        let (now, full, power) = if let Some(now) = read_f64(path, "energy_now") {
+            (
+                Some(now),
+                read_f64(path, "energy_full"),
+                read_f64(path, "power_now"),
+            )
+        } else if let (Some(now), Some(voltage)) = (read_f64(path, "charge_now"), voltage) {
+            (
+                Some(now * voltage),
+                read_f64(path, "charge_full").map(|x| x * voltage),
+                read_f64(path, "current_now").map(|x| x * voltage),
+            )
+        } else {
+            (None, None, None)
+        };
+
+        let capacity = match (read_f64(path, "capacity"), now, full) {
+            (Some(capacity), ..) => capacity,
+            (None, Some(now), Some(full)) if full > 0.0 => now / full * 100.0,
+            _ => return None,
+        };
+
+        Some(Self {
+            status: read(path, "status").unwrap_or("Unknown".to_string()),
+            capacity,
+            now,
+            full,
+            power: power.map(f64::abs),
+        })
+    }
+}
+
+fn format_remaining(hours: f64) -> String {
+    let minutes = (hours * 60.0).round() as u64;
+    format!("{}:{:0>2}", minutes / 60, minutes % 60)
+}
+
+async fn collect_battery(
+    s: UnboundedSender<Collection>,
+    item: ConfigItem,
+    root: PathBuf,
+) -> Result<()> {
+    let paths = if let Some(name) = item.value.as_deref() {
+        vec![root.join(name)]
+    } else {
+        supplies(&root, "BAT")?
+    };
+
+    let batteries = paths
+        .iter()
+        .filter_map(|x| Battery::read(x))
+        .collect::<Vec<Battery>>();
+
+    if batteries.is_empty() {
+        return Err(anyhow!("No battery could be found in '{}'", root.display()));
+    }
+
+    let ac = supplies(&root, "AC")?
+        .iter()
+        .any(|x| read(x, "online").as_deref() == Some("1"));
+
+    let status = batteries
+        .iter()
+        .map(|x| x.status.clone())
+        .find(|x| x == "Charging" || x == "Discharging")
+        .unwrap_or(batteries[0].status.clone());
+
+    let (now, full, power) = batteries.iter().fold((0.0, 0.0, 0.0), |acc, x| {
+        (
+            acc.0 + x.now.unwrap_or_default(),
+            acc.1 + x.full.unwrap_or_default(),
+            acc.2 + x.power.unwrap_or_default(),
+        )
+    });
+
+    let capacity = if full > 0.0 {
+        now / full * 100.0
+    } else {
+        batteries.iter().fold(0.0, |acc, x| acc + x.capacity) / batteries.len() as f64
+    };
+
+    let remaining = match status.as_str() {
+        _ if power <= 0.0 => None,
+        "Discharging" => Some(now / power),
+        "Charging" if full > now => Some((full - now) / power),
+        _ => None,
+    };
+
+    // urgency is measured in how depleted the battery is, so that the thresholds read the same
+    // as for the other blocks: the higher the value, the more urgent.
+    let depleted = (100.0 - capacity).max(0.0).floor() as u64;
+    let critical = item
+        .urgency
+        .map_or(DEFAULT_CRITICAL, |x| 100 - x.2.min(100) as u64);
+
+    Ok(s.send(
+        Collection::new(
+            item,
+            "{status} {pct}% {remaining|}",
+            vec![
+                ("pct".into(), Value::Float(capacity, Some(0))),
+                ("status".into(), status.clone().into()),
+                ("power".into(), Value::Float(power / 1000000.0, Some(1))),
+                ("ac".into(), if ac { "AC" } else { "" }.into()),
+            ]
+            .into_iter()
+            .chain(remaining.map(|x| ("remaining".into(), format_remaining(x).into())))
+            .collect(),
+            depleted,
+        )
+        .with_urgent(status == "Discharging" && (capacity.floor() as u64) < critical),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::test::*;
+
+    fn battery(root: &Path, name: &str, files: &[(&str, &str)]) {
+        for (file, content) in files {
+            write(root, &format!("{}/{}", name, file), content);
+        }
+    }
+
+    async fn collect_root(root: PathBuf, yaml: &str) -> Collection {
+        collect(&BatteryCollector::new(root), item(yaml))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn energy_remaining() {
+        let root = fake_root("battery-energy");
+        battery(
+            &root,
+            "BAT0",
+            &[
+                ("status", "Discharging"),
+                ("energy_now", "30000000"),
+                ("energy_full", "60000000"),
+                ("power_now", "15000000"),
+            ],
+        );
+        battery(&root, "AC", &[("online", "0")]);
+
+        let collection = collect_root(root, "name: bat\ntype: battery").await;
+        assert_eq!(rule(&collection, "pct"), "50");
+        assert_eq!(rule(&collection, "status"), "Discharging");
+        assert_eq!(rule(&collection, "remaining"), "2:00");
+        assert_eq!(rule(&collection, "power"), "15.0");
+        assert_eq!(rule(&collection, "ac"), "");
+        assert_eq!(collection.percent(), 50);
+    }
+
+    #[tokio::test]
+    async fn charge_is_converted_to_energy() {
+        let root = fake_root("battery-charge");
+        // 2Ah of 4Ah at 12V, charging at 1A
+        battery(
+            &root,
+            "BAT1",
+            &[
+                ("status", "Charging"),
+                ("charge_now", "2000000"),
+                ("charge_full", "4000000"),
+                ("current_now", "1000000"),
+                ("voltage_now", "12000000"),
+            ],
+        );
+        battery(&root, "AC", &[("online", "1")]);
+
+        let collection = collect_root(root, "name: bat\ntype: battery\nvalue: BAT1").await;
+        assert_eq!(rule(&collection, "pct"), "50");
+        assert_eq!(rule(&collection, "power"), "12.0");
+        assert_eq!(rule(&collection, "remaining"), "2:00");
+        assert_eq!(rule(&collection, "ac"), "AC");
+    }
+
+    #[tokio::test]
+    async fn capacity_without_energy() {
+        let root = fake_root("battery-capacity");
+        battery(&root, "BAT0", &[("status", "Full"), ("capacity", "97")]);
+
+        let collection = collect_root(root, "name: bat\ntype: battery").await;
+        assert_eq!(rule(&collection, "pct"), "97");
+        assert_eq!(rule(&collection, "status"), "Full");
+        assert!(collection.rules.iter().all(|x| x.0 != "remaining"));
+        assert_eq!(collection.percent(), 3);
+    }
+
+    #[tokio::test]
+    async fn urgent_threshold() {
+        let cases = [
+            ("Discharging", "4", "", true),
+            ("Discharging", "5", "", false),
+            ("Charging", "4", "", false),
+            // the last urgency threshold of 90 depleted is 10 percent charge
+            ("Discharging", "8", "\nurgency: [10, 50, 90]", true),
+            ("Discharging", "12", "\nurgency: [10, 50, 90]", false),
+        ];
+
+        for (i, (status, capacity, urgency, urgent)) in cases.into_iter().enumerate() {
+            let root = fake_root(&format!("battery-urgent-{}", i));
+            battery(&root, "BAT0", &[("status", status), ("capacity", capacity)]);
+
+            let collection =
+                collect_root(root, &format!("name: bat\ntype: battery{}", urgency)).await;
+            assert_eq!(collection.urgent, urgent, "{} at {}%", status, capacity);
+        }
+    }
+
+    #[tokio::test]
+    async fn no_battery() {
+        let root = fake_root("battery-none");
+        battery(&root, "AC", &[("online", "1")]);
+
+        let err = collect(
+            &BatteryCollector::new(root),
+            item("name: bat\ntype: battery"),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().starts_with("No battery could be found"));
+    }
+}
@@ -0,0 +1,548 @@
+use super::{command_collection, validate_format, Collection, Collector, CollectorFuture};
+use crate::config::{CommandItem, ConfigItem};
+use anyhow::{anyhow, Result};
+use nix::{
+    sys::signal::{killpg, Signal},
+    unistd::Pid,
+};
+use std::path::PathBuf;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::{Child, Command},
+    sync::mpsc::UnboundedSender,
+};
+
+// i3blocks commands exit with this to make their block urgent
+const URGENT_EXIT_CODE: i32 = 33;
+
+// characters that never need quoting in a shell word
+fn plain(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c)
+}
+
+// Quotes a word so the shell, or words(), reads it back unchanged.
+pub fn quote(word: &str) -> String {
+    if !word.is_empty() && word.chars().all(plain) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+// a `~` or `~/` at the start of a path is the home directory
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{}", home.display(), rest)
+        }
+        _ => path.to_string(),
+    }
+}
+
+// Splits a command into words with the quoting rules of the POSIX shell, expanding `~` and
+// `$VAR` or `${VAR}` where the shell would. Expanded variables are not split into more words,
+// and nothing else the shell does, such as globs or pipes, is supported.
+pub fn words(s: &str, var: impl Fn(&str) -> String) -> Result<Vec<String>> {
+    let mut res = Vec::new();
+    // the word being read, or None between words
+    let mut word: Option<String> = None;
+    let mut chars = s.chars().peekable();
+
+    let variable = |chars: &mut std::iter::Peekable<std::str::Chars>| -> Result<Option<String>> {
+        let name = if chars.next_if_eq(&'{').is_some() {
+            let mut name = String::new();
+            loop {
+                match chars.next() {
+                    Some('}') => break,
+                    Some(c) => name.push(c),
+                    None => return Err(anyhow!("Missing '}}' in '{}'", s)),
+                }
+            }
+            name
+        } else {
+            let mut name = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                name.push(c);
+            }
+            name
+        };
+
+        // a `$` on its own is kept as it is
+        Ok((!name.is_empty()).then(|| var(&name)))
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(word) = word.take() {
+                    res.push(word);
+                }
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(anyhow!("Missing closing ' in '{}'", s)),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('$' | '`' | '"' | '\\')) => word.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(anyhow!("Missing closing \" in '{}'", s)),
+                        },
+                        Some('$') => match variable(&mut chars)? {
+                            Some(value) => word.push_str(&value),
+                            None => word.push('$'),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(anyhow!("Missing closing \" in '{}'", s)),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err(anyhow!("Nothing to escape at the end of '{}'", s)),
+            },
+            '$' => {
+                let value = variable(&mut chars)?;
+                let word = word.get_or_insert_with(String::new);
+                match value {
+                    Some(value) => word.push_str(&value),
+                    None => word.push('$'),
+                }
+            }
+            '~' if word.is_none()
+                && chars.peek().is_none_or(|c| *c == '/' || c.is_whitespace()) =>
+            {
+                word = Some(expand_home("~"));
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    res.extend(word);
+    Ok(res)
+}
+
+// The variables a block's commands are run with: the block's own `env`, and where the block is.
+// i3blocks scripts are given the variables i3blocks sets, with the click they are run again for.
+fn environment(item: &ConfigItem) -> Vec<(String, String)> {
+    let mut res = vec![
+        ("YAIB_BLOCK_NAME".to_string(), item.name.clone()),
+        ("YAIB_BLOCK_TYPE".to_string(), item.typ.clone()),
+        ("YAIB_BLOCK_PAGE".to_string(), item.page.to_string()),
+    ];
+
+    if i3blocks(item) {
+        let instance: Option<String> = item.option("instance").ok().flatten();
+        res.push(("BLOCK_NAME".to_string(), item.name.clone()));
+        res.push(("BLOCK_INSTANCE".to_string(), instance.unwrap_or_default()));
+
+        if let Some(click) = &item.click {
+            res.extend(
+                [
+                    ("BLOCK_BUTTON", click.button.to_string()),
+                    ("BLOCK_MODIFIERS", click.modifiers.join(",")),
+                    ("BLOCK_X", click.x.to_string()),
+                    ("BLOCK_Y", click.y.to_string()),
+                    ("BLOCK_RELATIVE_X", click.relative_x.to_string()),
+                    ("BLOCK_RELATIVE_Y", click.relative_y.to_string()),
+                    ("BLOCK_WIDTH", click.width.to_string()),
+                    ("BLOCK_HEIGHT", click.height.to_string()),
+                ]
+                .map(|(k, v)| (k.to_string(), v)),
+            );
+        }
+    }
+
+    res.extend(item.env.clone().unwrap_or_default());
+    res
+}
+
+// A command run for a block, through `/bin/sh -c` when shell is set, otherwise split into words
+// and run directly. It runs in the block's `cwd` and environment.
+pub fn command(item: &ConfigItem, line: &str, shell: bool) -> Result<Command> {
+    let env = environment(item);
+
+    let mut command = if shell {
+        let mut command = Command::new("/bin/sh");
+        command.arg("-c").arg(line);
+        command
+    } else {
+        let var = |name: &str| {
+            env.iter()
+                .rev()
+                .find(|x| x.0 == name)
+                .map(|x| x.1.clone())
+                .or_else(|| std::env::var(name).ok())
+                .unwrap_or_default()
+        };
+        let words = words(line, var)?;
+        let Some((program, args)) = words.split_first() else {
+            return Err(anyhow!("Block '{}' has no command to run", item.name));
+        };
+        let mut command = Command::new(program);
+        command.args(args);
+        command
+    };
+
+    if let Some(cwd) = &item.cwd {
+        command.current_dir(PathBuf::from(expand_home(cwd)));
+    }
+
+    command.envs(env);
+    Ok(command)
+}
+
+// Kills the process group of a command if its collector is dropped, e.g. on timeout, so the
+// processes it started are stopped with it.
+struct ProcessGroup(Option<u32>);
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        if let Some(pid) = self.0 {
+            let _ = killpg(Pid::from_raw(pid as i32), Signal::SIGKILL);
+        }
+    }
+}
+
+pub struct CommandCollector;
+
+impl Collector for CommandCollector {
+    fn validate(&self, item: &ConfigItem) -> Result<()> {
+        item.option::<bool>("persist")?;
+        item.option::<bool>("shell")?;
+        item.option::<String>("output")?;
+        item.option::<String>("instance")?;
+
+        if item.value.as_deref().is_none_or(|x| x.trim().is_empty()) {
+            return Err(anyhow!(
+                "Command block '{}' must have a command as its value",
+                item.name
+            ));
+        }
+
+        if !matches!(output(item).as_deref(), None | Some("json" | "i3blocks")) {
+            return Err(anyhow!(
+                "Command block '{}' must have an output of 'json' or 'i3blocks'",
+                item.name
+            ));
+        }
+
+        if !shell(item) {
+            words(item.value.as_deref().unwrap_or_default(), |_| String::new())
+                .map_err(|e| anyhow!("Command block '{}': {}", item.name, e))?;
+        }
+
+        validate_format(item)
+    }
+
+    fn options(&self) -> &'static [&'static str] {
+        &["persist", "shell", "output", "instance"]
+    }
+
+    fn persists(&self) -> bool {
+        true
+    }
+
+    fn arguments(&self) -> bool {
+        true
+    }
+
+    fn collect(&self, s: UnboundedSender<Collection>, item: ConfigItem) -> CollectorFuture {
+        if item.persistent() {
+            Box::pin(collect_persistent(s, item))
+        } else {
+            Box::pin(collect_command(s, item))
+        }
+    }
+}
+
+fn spawn(item: &ConfigItem) -> Result<(Child, ProcessGroup)> {
+    let value = item.value.clone().unwrap_or_default();
+
+    let child = command(item, &value, shell(item))?
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .process_group(0)
+        .kill_on_drop(true)
+        .spawn()?;
+    let group = ProcessGroup(child.id());
+
+    Ok((child, group))
+}
+
+async fn collect_command(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
+    let (child, mut group) = spawn(&item)?;
+    let output = child.wait_with_output().await?;
+    group.0 = None;
+
+    if i3blocks(&item) {
+        return Ok(s.send(i3blocks_collection(item, &output)?)?);
+    }
+
+    let command: CommandItem = serde_json::from_slice(&output.stdout)?;
+
+    Ok(s.send(command_collection(item, command))?)
+}
+
+// the options are checked when the block is validated
+fn shell(item: &ConfigItem) -> bool {
+    item.option("shell").ok().flatten().unwrap_or(false)
+}
+
+fn output(item: &ConfigItem) -> Option<String> {
+    item.option("output").ok().flatten()
+}
+
+pub(crate) fn i3blocks(item: &ConfigItem) -> bool {
+    output(item).as_deref() == Some("i3blocks")
+}
+
+fn text(item: &ConfigItem, text: &str) -> CommandItem {
+    CommandItem {
+        name: item.name.clone(),
+        value: text.to_string(),
+        ..Default::default()
+    }
+}
+
+// The lines of i3blocks output are the full text, short text and color, and any other exit code
+// than 0 or 33 is a failure.
+fn i3blocks_collection(item: ConfigItem, output: &std::process::Output) -> Result<Collection> {
+    let urgent = output.status.code() == Some(URGENT_EXIT_CODE);
+    if !output.status.success() && !urgent {
+        return Err(anyhow!("command exited with {}", output.status));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    let mut command = text(&item, lines.next().unwrap_or_default());
+    let mut line = || {
+        lines
+            .next()
+            .filter(|x| !x.is_empty())
+            .map(ToString::to_string)
+    };
+    command.block.short_text = line();
+    command.block.color = line();
+
+    Ok(command_collection(item, command).with_urgent(urgent))
+}
+
+// Each line is a JSON object like the output of other commands, or the text to show. Lines of
+// i3blocks output are always the text to show.
+fn parse_line(item: &ConfigItem, line: &str) -> CommandItem {
+    if i3blocks(item) {
+        return text(item, line);
+    }
+
+    serde_json::from_str(line).unwrap_or_else(|_| text(item, line))
+}
+
+// The command runs until it exits, updating the block with every line it writes. It is then
+// started again once the update interval has passed since it exited, or with backoff from then
+// if it failed.
+async fn collect_persistent(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
+    let (mut child, mut group) = spawn(&item)?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("Could not read the output of '{}'", item.name))?;
+
+    let mut lines = BufReader::new(stdout).lines();
+    while let Some(line) = lines.next_line().await? {
+        s.send(command_collection(item.clone(), parse_line(&item, &line)))?;
+    }
+
+    let status = child.wait().await?;
+    group.0 = None;
+
+    if !status.success() {
+        return Err(anyhow!("command exited with {}", status));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(name: &str) -> String {
+        match name {
+            "NAME" => "value".to_string(),
+            "SPACED" => "a b".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn split(s: &str) -> Vec<String> {
+        words(s, var).unwrap()
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(split("  a  'b c'\t\"d e\" "), vec!["a", "b c", "d e"]);
+        assert_eq!(split("a'b'\"c\"d"), vec!["abcd"]);
+        assert_eq!(split("'' \"\""), vec!["", ""]);
+        assert_eq!(split("'a \"b\" $NAME \\'"), vec!["a \"b\" $NAME \\"]);
+        assert!(split("").is_empty());
+    }
+
+    #[test]
+    fn backslashes() {
+        assert_eq!(split("a\\ b c"), vec!["a b", "c"]);
+        assert_eq!(split("\\'a\\\""), vec!["'a\""]);
+        assert_eq!(split("\"a\\\"b\\\\c\\$d\""), vec!["a\"b\\c$d"]);
+        // other escapes in double quotes keep the backslash
+        assert_eq!(split("\"a\\nb\""), vec!["a\\nb"]);
+        assert_eq!(split("a\\\nb"), vec!["ab"]);
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(split("$NAME ${NAME}s"), vec!["value", "values"]);
+        assert_eq!(split("\"$NAME-${NAME}\""), vec!["value-value"]);
+        assert_eq!(split("x$UNSET"), vec!["x"]);
+        // expanded variables are not split into words
+        assert_eq!(split("$SPACED"), vec!["a b"]);
+        assert_eq!(split("a $ \"$\""), vec!["a", "$", "$"]);
+    }
+
+    #[test]
+    fn home() {
+        let home = dirs::home_dir().unwrap().display().to_string();
+
+        assert_eq!(
+            split("~ ~/bin"),
+            vec![home.clone(), format!("{}/bin", home)]
+        );
+        assert_eq!(split("a~ '~' \"~\" ~user"), vec!["a~", "~", "~", "~user"]);
+    }
+
+    #[test]
+    fn errors() {
+        for s in ["'a", "\"a", "a\\", "${NAME", "\"${NAME\""] {
+            assert!(words(s, var).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn quoted_words_round_trip() {
+        let argv = [
+            "plain",
+            "",
+            "a b",
+            "it's",
+            "\"quoted\"",
+            "$NAME",
+            "${NAME}",
+            "~",
+            "back\\slash",
+            "tab\tnewline\n",
+            "ünïcode",
+        ];
+
+        let line = argv.iter().map(|x| quote(x)).collect::<Vec<_>>().join(" ");
+        assert_eq!(split(&line), argv);
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn value_lists_are_quoted() {
+        let item = crate::collectors::test::item(
+            "name: c\ntype: command\nvalue: [echo, a b, \"it's\", '']",
+        );
+
+        assert_eq!(
+            split(item.value.as_deref().unwrap()),
+            vec!["echo", "a b", "it's", ""]
+        );
+    }
+
+    fn output(code: i32, stdout: &str) -> std::process::Output {
+        use std::os::unix::process::ExitStatusExt;
+
+        std::process::Output {
+            // the wait status of a process that exited, with its code in the second byte
+            status: std::process::ExitStatus::from_raw(code << 8),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn i3blocks_output() {
+        use crate::collectors::test::{item, render};
+
+        let item = item("name: c\ntype: command\nvalue: script\noutput: i3blocks");
+
+        let block = render(
+            &i3blocks_collection(item.clone(), &output(0, "full\nshort\n#00FF00\n")).unwrap(),
+        )
+        .await;
+        assert_eq!(block.full_text, "full");
+        assert_eq!(block.short_text.as_deref(), Some("short"));
+        assert_eq!(block.color.as_deref(), Some("#00FF00"));
+        assert_eq!(block.urgent, None);
+
+        // empty lines leave the field unset
+        let block =
+            render(&i3blocks_collection(item.clone(), &output(0, "full\n\n")).unwrap()).await;
+        assert_eq!(block.full_text, "full");
+        assert_eq!(block.short_text, None);
+        assert_eq!(block.color, None);
+
+        let block =
+            render(&i3blocks_collection(item.clone(), &output(33, "alert\n")).unwrap()).await;
+        assert_eq!(block.full_text, "alert");
+        assert_eq!(block.urgent, Some(true));
+
+        assert!(i3blocks_collection(item, &output(1, "failed\n")).is_err());
+    }
+
+    #[test]
+    fn i3blocks_environment() {
+        let var = |item: &ConfigItem, name: &str| {
+            environment(item)
+                .into_iter()
+                .find(|x| x.0 == name)
+                .map(|x| x.1)
+        };
+
+        let mut item = crate::collectors::test::item(
+            "name: c\ntype: command\nvalue: script\noutput: i3blocks\ninstance: /home",
+        );
+        assert_eq!(var(&item, "BLOCK_NAME").as_deref(), Some("c"));
+        assert_eq!(var(&item, "BLOCK_INSTANCE").as_deref(), Some("/home"));
+        assert_eq!(var(&item, "BLOCK_BUTTON"), None);
+
+        item.click = Some(crate::bar::Click {
+            name: "c".to_string(),
+            button: 3,
+            modifiers: vec!["Shift".to_string(), "Mod4".to_string()],
+            ..Default::default()
+        });
+        assert_eq!(var(&item, "BLOCK_BUTTON").as_deref(), Some("3"));
+        assert_eq!(var(&item, "BLOCK_MODIFIERS").as_deref(), Some("Shift,Mod4"));
+
+        // other commands are not i3blocks scripts
+        let item = crate::collectors::test::item("name: c\ntype: command\nvalue: script");
+        assert_eq!(var(&item, "BLOCK_NAME"), None);
+    }
+}
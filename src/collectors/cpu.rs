@@ -0,0 +1,354 @@
+use super::{validate_format, Collection, Collector, CollectorFuture};
+use crate::{
+    config::ConfigItem,
+    formatter::{spark, Value},
+};
+use anyhow::{anyhow, Result};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+// relative to the root of the collector
+const STAT: &str = "stat";
+
+// Times spent in each state since boot, in clock ticks.
+#[derive(Debug, Clone, Copy, Default)]
+struct Times {
+    user: u64,
+    nice: u64,
+    system: u64,
+    idle: u64,
+    iowait: u64,
+    irq: u64,
+    softirq: u64,
+    steal: u64,
+}
+
+impl Times {
+    fn parse(fields: &[u64]) -> Self {
+        let field = |i: usize| fields.get(i).copied().unwrap_or_default();
+
+        Self {
+            user: field(0),
+            nice: field(1),
+            system: field(2),
+            idle: field(3),
+            iowait: field(4),
+            irq: field(5),
+            softirq: field(6),
+            steal: field(7),
+        }
+    }
+
+    // guest time is already counted in user time, so it is left out
+    fn total(&self) -> u64 {
+        self.user
+            + self.nice
+            + self.system
+            + self.idle
+            + self.iowait
+            + self.irq
+            + self.softirq
+            + self.steal
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Usage {
+    usage: f64,
+    user: f64,
+    system: f64,
+    iowait: f64,
+    steal: f64,
+}
+
+impl Usage {
+    // without a previous snapshot, this is the usage since boot
+    fn between(last: Option<&Times>, now: &Times) -> Self {
+        let last = last.copied().unwrap_or_default();
+        let total = now.total().saturating_sub(last.total());
+        if total == 0 {
+            return Self::default();
+        }
+
+        let pct =
+            |f: fn(&Times) -> u64| f(now).saturating_sub(f(&last)) as f64 / total as f64 * 100.0;
+
+        Self {
+            usage: 100.0 - pct(|x| x.idle + x.iowait),
+            user: pct(|x| x.user + x.nice),
+            system: pct(|x| x.system + x.irq + x.softirq),
+            iowait: pct(|x| x.iowait),
+            steal: pct(|x| x.steal),
+        }
+    }
+}
+
+// The aggregate is first, followed by each core.
+fn snapshot(root: &Path) -> Result<Vec<Times>> {
+    let path = root.join(STAT);
+    let res = std::fs::read_to_string(&path)?
+        .lines()
+        .filter(|line| line.starts_with("cpu"))
+        .map(|line| {
+            Times::parse(
+                &line
+                    .split_whitespace()
+                    .skip(1)
+                    .map(|x| x.parse::<u64>().unwrap_or_default())
+                    .collect::<Vec<u64>>(),
+            )
+        })
+        .collect::<Vec<Times>>();
+
+    if res.len() < 2 {
+        return Err(anyhow!("No CPUs could be found in '{}'", path.display()));
+    }
+
+    Ok(res)
+}
+
+// Each core as a vertical bar, colored by its urgency when the block uses pango markup.
+fn core_bars(item: &ConfigItem, cores: &[Usage]) -> String {
+    let mut usage = cores
+        .iter()
+        .map(|x| x.usage.round() as u64)
+        .collect::<Vec<u64>>();
+    if item.value.as_deref() == Some("load") {
+        usage.sort_by(|a, b| b.cmp(a));
+    }
+
+    let pango = item.markup.as_deref() == Some("pango");
+    usage
+        .into_iter()
+        .map(|pct| match item.urgency_color(pct).filter(|_| pango) {
+            Some(color) => format!("<span foreground=\"{}\">{}</span>", color, spark(pct)),
+            None => spark(pct).to_string(),
+        })
+        .collect()
+}
+
+// The previous snapshot of each block is kept so usage can be computed between runs.
+#[derive(Debug, Clone)]
+pub struct CpuCollector {
+    root: PathBuf,
+    snapshots: Arc<Mutex<HashMap<String, Vec<Times>>>>,
+}
+
+impl Default for CpuCollector {
+    fn default() -> Self {
+        Self::new("/proc".into())
+    }
+}
+
+impl CpuCollector {
+    // root is the directory holding stat, normally /proc
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            snapshots: Arc::default(),
+        }
+    }
+}
+
+impl Collector for CpuCollector {
+    fn validate(&self, item: &ConfigItem) -> Result<()> {
+        if !matches!(item.value.as_deref(), None | Some("index" | "load")) {
+            return Err(anyhow!(
+                "CPU block '{}' must be ordered by 'index' or 'load'",
+                item.name
+            ));
+        }
+
+        validate_format(item)
+    }
+
+    fn placeholders(&self) -> Option<&'static [&'static str]> {
+        Some(&[
+            "count", "usage", "user", "system", "iowait", "steal", "cores", "core*",
+        ])
+    }
+
+    fn collect(&self, s: UnboundedSender<Collection>, item: ConfigItem) -> CollectorFuture {
+        Box::pin(collect_cpu(
+            s,
+            item,
+            self.root.clone(),
+            self.snapshots.clone(),
+        ))
+    }
+}
+
+async fn collect_cpu(
+    s: UnboundedSender<Collection>,
+    item: ConfigItem,
+    root: PathBuf,
+    snapshots: Arc<Mutex<HashMap<String, Vec<Times>>>>,
+) -> Result<()> {
+    let now = snapshot(&root)?;
+    let last = snapshots
+        .lock()
+        .unwrap()
+        .insert(item.name.clone(), now.clone())
+        .unwrap_or_default();
+
+    let usage = now
+        .iter()
+        .enumerate()
+        .map(|(i, times)| Usage::between(last.get(i), times))
+        .collect::<Vec<Usage>>();
+    let total = usage[0];
+    let cores = &usage[1..];
+
+    let mut rules = vec![
+        ("count".into(), cores.len().into()),
+        ("usage".into(), Value::Float(total.usage, Some(2))),
+        ("user".into(), Value::Float(total.user, Some(2))),
+        ("system".into(), Value::Float(total.system, Some(2))),
+        ("iowait".into(), Value::Float(total.iowait, Some(2))),
+        ("steal".into(), Value::Float(total.steal, Some(2))),
+        ("cores".into(), core_bars(&item, cores).into()),
+    ];
+
+    for (i, core) in cores.iter().enumerate() {
+        rules.push((
+            format!("core{}", i).into(),
+            Value::Float(core.usage, Some(2)),
+        ));
+    }
+
+    Ok(s.send(Collection::new(
+        item,
+        "cpus: {count}, usage: {usage}",
+        rules,
+        total.usage.floor() as u64,
+    ))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::test::*;
+
+    fn times(user: u64, system: u64, idle: u64, iowait: u64) -> Times {
+        Times {
+            user,
+            system,
+            idle,
+            iowait,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn usage_between() {
+        // without a previous snapshot, the usage since boot
+        let usage = Usage::between(None, &times(30, 10, 50, 10));
+        assert_eq!(usage.usage, 40.0);
+        assert_eq!(usage.user, 30.0);
+        assert_eq!(usage.system, 10.0);
+        assert_eq!(usage.iowait, 10.0);
+
+        let usage = Usage::between(Some(&times(30, 10, 50, 10)), &times(60, 20, 100, 20));
+        assert_eq!(usage.usage, 40.0);
+        assert_eq!(usage.user, 30.0);
+
+        // nothing happened in between
+        let usage = Usage::between(Some(&times(30, 10, 50, 10)), &times(30, 10, 50, 10));
+        assert_eq!(usage.usage, 0.0);
+    }
+
+    #[test]
+    fn counters_going_backwards() {
+        // e.g. after a CPU went offline, the counters are treated as not having moved
+        let usage = Usage::between(Some(&times(60, 20, 100, 20)), &times(30, 10, 50, 10));
+        assert_eq!(usage.usage, 0.0);
+        assert_eq!(usage.user, 0.0);
+
+        // a single counter going backwards counts as none of the time
+        let usage = Usage::between(Some(&times(30, 10, 50, 10)), &times(80, 5, 100, 15));
+        assert_eq!(usage.user, 50.0);
+        assert_eq!(usage.system, 0.0);
+        assert_eq!(usage.usage.round(), 45.0);
+    }
+
+    #[tokio::test]
+    async fn stat() {
+        let root = fake_root("cpu");
+        let stat = |root: &Path, busy: u64| {
+            write(
+                root,
+                STAT,
+                &format!(
+                    "cpu  {busy} 0 0 100 0 0 0 0 0 0\ncpu0 {busy} 0 0 50 0 0 0 0 0 0\ncpu1 0 0 0 50 0 0 0 0 0 0\nintr 1\n"
+                ),
+            )
+        };
+        let collector = CpuCollector::new(root.clone());
+        let item = || item("name: cpu\ntype: cpu");
+
+        stat(&root, 100);
+        let collection = collect(&collector, item()).await.unwrap();
+        assert_eq!(rule(&collection, "count"), "2");
+        assert_eq!(rule(&collection, "usage"), "50.00");
+        assert_eq!(rule(&collection, "core0"), "66.67");
+
+        // usage is measured from the previous run of the block
+        stat(&root, 200);
+        let collection = collect(&collector, item()).await.unwrap();
+        assert_eq!(rule(&collection, "usage"), "100.00");
+        assert_eq!(rule(&collection, "core1"), "0.00");
+
+        write(&root, STAT, "intr 1\n");
+        assert!(collect(&collector, item()).await.is_err());
+    }
+
+    fn cores(usage: &[f64]) -> Vec<Usage> {
+        usage
+            .iter()
+            .map(|x| Usage {
+                usage: *x,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn core_bars_order() {
+        let cores = cores(&[10.0, 90.0, 50.0]);
+        let bars = |yaml| core_bars(&item(yaml), &cores);
+
+        let expected = [spark(10), spark(90), spark(50)].iter().collect::<String>();
+        assert_eq!(bars("name: cpu\ntype: cpu"), expected);
+        assert_eq!(bars("name: cpu\ntype: cpu\nvalue: index"), expected);
+        assert_eq!(
+            bars("name: cpu\ntype: cpu\nvalue: load"),
+            [spark(90), spark(50), spark(10)].iter().collect::<String>()
+        );
+    }
+
+    #[test]
+    fn core_bars_markup() {
+        let cores = cores(&[10.0, 90.0]);
+        let bars = |yaml: &str| core_bars(&item(yaml), &cores);
+        let urgency =
+            "urgency: [20, 50, 80]\nurgency_colors: [\"#111111\", \"#222222\", \"#333333\"]";
+
+        assert_eq!(
+            bars(&format!("name: cpu\ntype: cpu\nmarkup: pango\n{}", urgency)),
+            format!(
+                "{}<span foreground=\"#333333\">{}</span>",
+                spark(10),
+                spark(90)
+            )
+        );
+
+        // spans only show with pango markup, and only for cores with an urgency color
+        let plain = format!("{}{}", spark(10), spark(90));
+        assert_eq!(bars(&format!("name: cpu\ntype: cpu\n{}", urgency)), plain);
+        assert_eq!(bars("name: cpu\ntype: cpu\nmarkup: pango"), plain);
+    }
+}
@@ -0,0 +1,174 @@
+use super::{read, Collection, Collector, CollectorFuture};
+use crate::{config::ConfigItem, formatter::Value};
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc::UnboundedSender;
+
+pub const SYS_DEVICES_CPU: &str = "/sys/devices/system/cpu";
+
+// root is the directory holding the CPUs, normally /sys/devices/system/cpu
+rooted_collector!(CpufreqCollector, SYS_DEVICES_CPU);
+
+impl Collector for CpufreqCollector {
+    fn placeholders(&self) -> Option<&'static [&'static str]> {
+        Some(&["avg", "min", "max", "limit", "governor", "epp"])
+    }
+
+    fn collect(&self, s: UnboundedSender<Collection>, item: ConfigItem) -> CollectorFuture {
+        Box::pin(collect_cpufreq(s, item, self.root.clone()))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Policy {
+    // these are in kHz
+    cur: f64,
+    max: f64,
+    governor: String,
+    epp: String,
+}
+
+impl Policy {
+    fn read(path: &Path) -> Option<Self> {
+        Some(Self {
+            cur: read(path, "scaling_cur_freq")?.parse().ok()?,
+            max: read(path, "scaling_max_freq")?.parse().ok()?,
+            governor: read(path, "scaling_governor").unwrap_or_default(),
+            epp: read(path, "energy_performance_preference").unwrap_or_default(),
+        })
+    }
+}
+
+fn policies(root: &Path) -> Result<Vec<Policy>> {
+    let mut paths = Vec::new();
+
+    for entry in std::fs::read_dir(root)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name
+            .strip_prefix("cpu")
+            .is_some_and(|x| !x.is_empty() && x.chars().all(|c| c.is_ascii_digit()))
+        {
+            paths.push(entry.path().join("cpufreq"));
+        }
+    }
+
+    paths.sort();
+    Ok(paths.iter().filter_map(|x| Policy::read(x)).collect())
+}
+
+// the most common value, so a single core in another mode does not hide what the rest are in
+fn most_common(values: impl Iterator<Item = String>) -> String {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for value in values {
+        match counts.iter_mut().find(|x| x.0 == value) {
+            Some(count) => count.1 += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|x| x.1)
+        .map(|x| x.0)
+        .unwrap_or_default()
+}
+
+async fn collect_cpufreq(
+    s: UnboundedSender<Collection>,
+    item: ConfigItem,
+    root: PathBuf,
+) -> Result<()> {
+    let policies = policies(&root)?;
+    if policies.is_empty() {
+        return Err(anyhow!(
+            "No CPU frequencies could be found in '{}'",
+            root.display()
+        ));
+    }
+
+    let count = policies.len() as f64;
+    let avg = policies.iter().fold(0.0, |acc, x| acc + x.cur) / count;
+    let limit = policies.iter().fold(0.0, |acc, x| acc + x.max) / count;
+    let min = policies.iter().map(|x| x.cur).fold(f64::MAX, f64::min);
+    let max = policies.iter().map(|x| x.cur).fold(0.0, f64::max);
+
+    let ghz = |khz: f64| Value::Float(khz / 1000000.0, Some(2));
+    let pct = if limit > 0.0 {
+        (avg / limit * 100.0).floor() as u64
+    } else {
+        0
+    };
+
+    Ok(s.send(Collection::new(
+        item,
+        "{avg} GHz {governor}",
+        vec![
+            ("avg".into(), ghz(avg)),
+            ("min".into(), ghz(min)),
+            ("max".into(), ghz(max)),
+            ("limit".into(), ghz(limit)),
+            (
+                "governor".into(),
+                most_common(policies.iter().map(|x| x.governor.clone())).into(),
+            ),
+            (
+                "epp".into(),
+                most_common(policies.iter().map(|x| x.epp.clone())).into(),
+            ),
+        ],
+        pct,
+    ))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::test::*;
+
+    fn cpu(root: &Path, cpu: usize, cur: &str, max: &str, governor: &str) {
+        let dir = format!("cpu{}/cpufreq", cpu);
+        write(root, &format!("{}/scaling_cur_freq", dir), cur);
+        write(root, &format!("{}/scaling_max_freq", dir), max);
+        write(root, &format!("{}/scaling_governor", dir), governor);
+        write(
+            root,
+            &format!("{}/energy_performance_preference", dir),
+            "balance_performance",
+        );
+    }
+
+    #[tokio::test]
+    async fn frequencies() {
+        let root = fake_root("cpufreq");
+        cpu(&root, 0, "1000000", "4000000", "powersave");
+        cpu(&root, 1, "3000000", "4000000", "powersave");
+        cpu(&root, 2, "2000000", "4000000", "performance");
+        // directories that are not CPUs are skipped
+        write(&root, "cpufreq/boost", "1");
+        write(&root, "cpuidle/current_driver", "intel_idle");
+
+        let collection = collect(&CpufreqCollector::new(root), item("name: f\ntype: cpufreq"))
+            .await
+            .unwrap();
+        assert_eq!(rule(&collection, "avg"), "2.00");
+        assert_eq!(rule(&collection, "min"), "1.00");
+        assert_eq!(rule(&collection, "max"), "3.00");
+        assert_eq!(rule(&collection, "limit"), "4.00");
+        assert_eq!(rule(&collection, "governor"), "powersave");
+        assert_eq!(rule(&collection, "epp"), "balance_performance");
+        assert_eq!(collection.percent(), 50);
+    }
+
+    #[tokio::test]
+    async fn no_frequencies() {
+        let root = fake_root("cpufreq-none");
+        write(&root, "cpu0/online", "1");
+
+        assert!(
+            collect(&CpufreqCollector::new(root), item("name: f\ntype: cpufreq"))
+                .await
+                .is_err()
+        );
+    }
+}
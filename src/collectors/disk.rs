@@ -0,0 +1,300 @@
+use super::{validate_format, Collection, Collector, CollectorFuture};
+use crate::{config::ConfigItem, formatter::Value};
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use tokio::sync::mpsc::UnboundedSender;
+
+// relative to the root holding proc, normally /
+const PROC_MOUNTINFO: &str = "proc/self/mountinfo";
+
+// filesystems shown when the block names no mounts or filesystem types
+const DEFAULT_FSTYPES: &[&str] = &[
+    "ext2", "ext3", "ext4", "btrfs", "xfs", "zfs", "f2fs", "bcachefs", "jfs", "reiserfs", "vfat",
+    "exfat", "ntfs", "ntfs3", "fuseblk", "hfsplus", "apfs",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mount {
+    pub point: String,
+    // major:minor, the same for every mount of a filesystem
+    pub number: String,
+    pub fstype: String,
+    pub source: String,
+}
+
+// mountinfo escapes spaces and other whitespace as octal, e.g. `\040`
+fn unescape(s: &str) -> String {
+    let mut res = String::new();
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let octal = chars.clone().take(3).collect::<String>();
+            if let Some(x) = (octal.len() == 3)
+                .then(|| u8::from_str_radix(&octal, 8).ok())
+                .flatten()
+            {
+                res.push(x as char);
+                chars.nth(2);
+                continue;
+            }
+        }
+        res.push(c);
+    }
+
+    res
+}
+
+pub fn mounts(root: &Path) -> Result<Vec<Mount>> {
+    let mut res = Vec::new();
+
+    for line in std::fs::read_to_string(root.join(PROC_MOUNTINFO))?.lines() {
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        let Some(separator) = fields.iter().position(|x| *x == "-") else {
+            continue;
+        };
+        if fields.len() < separator + 3 || separator < 5 {
+            continue;
+        }
+
+        res.push(Mount {
+            point: unescape(fields[4]),
+            number: fields[2].to_string(),
+            fstype: fields[separator + 1].to_string(),
+            source: unescape(fields[separator + 2]),
+        });
+    }
+
+    Ok(res)
+}
+
+// `*` matches any run of characters and `?` any single character
+fn glob(pattern: &str, s: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let s = s.chars().collect::<Vec<char>>();
+    let (mut p, mut i) = (0, 0);
+    let mut star = None;
+
+    while i < s.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == s[i]) {
+            p += 1;
+            i += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, i));
+            p += 1;
+        } else if let Some((sp, si)) = star {
+            p = sp + 1;
+            i = si + 1;
+            star = Some((sp, si + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|x| *x == '*')
+}
+
+// Sizes are in bytes. Free space includes the space reserved for root, which available space
+// does not.
+#[derive(Debug, Clone, Copy, Default)]
+struct Usage {
+    total: u64,
+    used: u64,
+    free: u64,
+    avail: u64,
+    inodes: u64,
+    inodes_free: u64,
+}
+
+fn usage(point: &str) -> Result<Usage> {
+    let stats = nix::sys::statvfs::statvfs(point)
+        .map_err(|e| anyhow!("Could not read the usage of '{}': {}", point, e))?;
+
+    let size = stats.fragment_size() as u64;
+    Ok(Usage {
+        total: stats.blocks() as u64 * size,
+        used: (stats.blocks() as u64).saturating_sub(stats.blocks_available() as u64) * size,
+        free: stats.blocks_free() as u64 * size,
+        avail: stats.blocks_available() as u64 * size,
+        inodes: stats.files() as u64,
+        inodes_free: stats.files_free() as u64,
+    })
+}
+
+// The mounts a block shows, without mounts of the same filesystem in more than one place.
+fn selected(item: &ConfigItem, mounts: Vec<Mount>) -> Result<Vec<Mount>> {
+    let patterns = item.option::<Vec<String>>("mounts")?;
+    let fstypes = match (item.option::<Vec<String>>("fstypes")?, &patterns) {
+        (Some(fstypes), _) => Some(fstypes),
+        (None, Some(_)) => None,
+        (None, None) => Some(DEFAULT_FSTYPES.iter().map(ToString::to_string).collect()),
+    };
+
+    let mut res: Vec<Mount> = Vec::new();
+    for mount in mounts {
+        if patterns
+            .as_ref()
+            .is_some_and(|x| !x.iter().any(|pattern| glob(pattern, &mount.point)))
+        {
+            continue;
+        }
+
+        if fstypes.as_ref().is_some_and(|x| !x.contains(&mount.fstype)) {
+            continue;
+        }
+
+        if !res.iter().any(|x| x.number == mount.number) {
+            res.push(mount);
+        }
+    }
+
+    Ok(res)
+}
+
+pub struct DiskCollector;
+
+impl Collector for DiskCollector {
+    fn validate(&self, item: &ConfigItem) -> Result<()> {
+        let mounts = item.option::<Vec<String>>("mounts")?;
+        let fstypes = item.option::<Vec<String>>("fstypes")?;
+        if item.value.as_ref().is_some_and(|x| x.is_list()) {
+            return Err(anyhow!(
+                "Disk block '{}' takes a single mount point as its value; list several in mounts",
+                item.name
+            ));
+        }
+
+        if item.value.is_some() && (mounts.is_some() || fstypes.is_some()) {
+            return Err(anyhow!(
+                "Disk block '{}' must have either a value or mounts and fstypes, not both",
+                item.name
+            ));
+        }
+
+        validate_format(item)
+    }
+
+    fn options(&self) -> &'static [&'static str] {
+        &["mounts", "fstypes"]
+    }
+
+    fn placeholders(&self) -> Option<&'static [&'static str]> {
+        Some(&[
+            "mount",
+            "total",
+            "usage",
+            "pct",
+            "free",
+            "avail",
+            "inodes_pct",
+            "fstype",
+            "device",
+        ])
+    }
+
+    fn collect(&self, s: UnboundedSender<Collection>, item: ConfigItem) -> CollectorFuture {
+        Box::pin(collect_disk(s, item))
+    }
+}
+
+fn pct(used: u64, total: u64) -> f64 {
+    if total > 0 {
+        used as f64 / total as f64 * 100.0
+    } else {
+        0.0
+    }
+}
+
+// The urgency is whichever of space and inodes is closer to running out, as either stops
+// files from being written.
+fn rules(mount: &Mount, usage: Usage) -> (crate::formatter::Rules<'static>, f64) {
+    let space = pct(usage.used, usage.total);
+    // filesystems like btrfs have no fixed number of inodes, and report none
+    let inodes = pct(usage.inodes.saturating_sub(usage.inodes_free), usage.inodes);
+
+    (
+        vec![
+            ("mount".into(), mount.point.clone().into()),
+            ("total".into(), Value::Bytes(usage.total as f64)),
+            ("usage".into(), Value::Bytes(usage.used as f64)),
+            ("pct".into(), Value::Float(space, Some(1))),
+            ("free".into(), Value::Bytes(usage.free as f64)),
+            ("avail".into(), Value::Bytes(usage.avail as f64)),
+            ("inodes_pct".into(), Value::Float(inodes, Some(1))),
+            ("fstype".into(), mount.fstype.clone().into()),
+            ("device".into(), mount.source.clone().into()),
+        ],
+        space.max(inodes),
+    )
+}
+
+async fn collect_disk(s: UnboundedSender<Collection>, item: ConfigItem) -> Result<()> {
+    let mounts = mounts(Path::new("/"))?;
+
+    // a single mount point is expected to be there, unlike those found by a pattern or type,
+    // which come and go with removable drives
+    if let Some(value) = item.value.as_deref() {
+        let mount = mounts
+            .iter()
+            .rev()
+            .find(|x| x.point == value)
+            .ok_or_else(|| anyhow!("Mount point '{}' could not be found", value))?;
+        let (rules, pct) = rules(mount, usage(value)?);
+
+        return Ok(s.send(Collection::new(
+            item,
+            "total: {total}, usage: {usage}",
+            rules,
+            pct.floor() as u64,
+        ))?);
+    }
+
+    let mut segments = Vec::new();
+    for mount in selected(&item, mounts)? {
+        // a drive can be removed between reading the mounts and reading its usage
+        if let Ok(usage) = usage(&mount.point) {
+            segments.push(rules(&mount, usage));
+        }
+    }
+
+    let Some(fullest) = segments.iter().max_by(|a, b| a.1.total_cmp(&b.1)).cloned() else {
+        return Ok(s.send(Collection::text(item, String::new(), 0))?);
+    };
+
+    Ok(s.send(
+        Collection::new(item, "{mount}: {pct}%", fullest.0, fullest.1.floor() as u64)
+            .with_segments(segments.into_iter().map(|x| x.0).collect()),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        assert!(glob("/", "/"));
+        assert!(glob("/mnt/*", "/mnt/usb"));
+        assert!(glob("/mnt/*", "/mnt/"));
+        assert!(glob("*", ""));
+        assert!(glob("/media/*/disk?", "/media/me/disk1"));
+        assert!(glob("/mnt/?", "/mnt/é"));
+        assert!(glob("*a*b*", "xxaxxbxx"));
+        assert!(glob("*ab", "aab"));
+        assert!(!glob("/mnt/*", "/mn"));
+        assert!(!glob("/mnt/?", "/mnt/"));
+        assert!(!glob("/mnt", "/mnt/usb"));
+        assert!(!glob("*a*b", "xxaxxbxx"));
+    }
+
+    #[test]
+    fn unescapes() {
+        assert_eq!(unescape("/mnt/my\\040disk"), "/mnt/my disk");
+        assert_eq!(unescape("tab\\011and\\012line"), "tab\tand\nline");
+        assert_eq!(unescape("back\\134slash"), "back\\slash");
+        // anything that is not three octal digits is kept as it is
+        assert_eq!(unescape("a\\08b"), "a\\08b");
+        assert_eq!(unescape("end\\04"), "end\\04");
+        assert_eq!(unescape("plain"), "plain");
+    }
+}
@@ -0,0 +1,272 @@
+use super::{
+    disk::mounts, validate_format, Collection, Collector, CollectorFuture, Sample, Samples,
+};
+use crate::{config::ConfigItem, formatter::Value};
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc::UnboundedSender;
+
+// relative to the root of the collector
+const PROC_DISKSTATS: &str = "proc/diskstats";
+
+// diskstats counts sectors of 512 bytes, whatever the sector size of the device
+const SECTOR_SIZE: f64 = 512.0;
+
+// samples are of the reads, sectors read, writes, sectors written and milliseconds spent doing
+// I/O
+#[derive(Debug, Clone)]
+pub struct DiskioCollector {
+    root: PathBuf,
+    samples: Samples<5>,
+}
+
+impl Default for DiskioCollector {
+    fn default() -> Self {
+        Self::new("/".into())
+    }
+}
+
+impl DiskioCollector {
+    // root is the directory holding proc, normally /
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            samples: Samples::default(),
+        }
+    }
+}
+
+impl Collector for DiskioCollector {
+    fn validate(&self, item: &ConfigItem) -> Result<()> {
+        if item.value.is_none() {
+            return Err(anyhow!(
+                "Disk I/O block '{}' must have a value naming a device or mount point",
+                item.name
+            ));
+        }
+
+        validate_format(item)
+    }
+
+    fn placeholders(&self) -> Option<&'static [&'static str]> {
+        Some(&[
+            "device",
+            "read_rate",
+            "write_rate",
+            "read_iops",
+            "write_iops",
+            "iops",
+            "busy",
+        ])
+    }
+
+    fn collect(&self, s: UnboundedSender<Collection>, item: ConfigItem) -> CollectorFuture {
+        Box::pin(collect_diskio(
+            s,
+            item,
+            self.root.clone(),
+            self.samples.clone(),
+        ))
+    }
+}
+
+// Bytes and operations per second, and the percentage of the time the device was busy.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Io {
+    read_rate: f64,
+    write_rate: f64,
+    read_iops: f64,
+    write_iops: f64,
+    busy: f64,
+}
+
+impl Io {
+    // without a previous sample, nothing is known to have happened yet
+    fn between(last: Option<&Sample<5>>, now: &Sample<5>) -> Self {
+        let [reads, read_sectors, writes, written_sectors, io_ticks] = now.rates(last);
+
+        Self {
+            read_rate: read_sectors * SECTOR_SIZE,
+            write_rate: written_sectors * SECTOR_SIZE,
+            read_iops: reads,
+            write_iops: writes,
+            // milliseconds spent doing I/O each second
+            busy: (io_ticks / 10.0).min(100.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Device {
+    Name(String),
+    Number(String),
+}
+
+// Mounts are matched to their device by number, which also works for device mapper and similar
+// devices. Filesystems like btrfs report a number of their own, so the source device is tried
+// as well.
+fn mount_devices(root: &Path, mount: &str) -> Result<Vec<Device>> {
+    let mount = mounts(root)?
+        .into_iter()
+        .rev()
+        .find(|x| x.point == mount)
+        .ok_or_else(|| anyhow!("Mount point '{}' could not be found", mount))?;
+
+    let source = Path::new(&mount.source);
+    let source = std::fs::canonicalize(source).unwrap_or(source.to_path_buf());
+    let mut res = vec![Device::Number(mount.number)];
+    if let Some(name) = source.file_name() {
+        res.push(Device::Name(name.to_string_lossy().to_string()));
+    }
+
+    Ok(res)
+}
+
+fn stats(root: &Path, devices: &[Device]) -> Result<(String, Sample<5>)> {
+    let path = root.join(PROC_DISKSTATS);
+    let content = std::fs::read_to_string(&path)?;
+    let lines = content
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+        .filter(|fields| fields.len() >= 13)
+        .collect::<Vec<Vec<&str>>>();
+
+    for device in devices {
+        let found = lines.iter().find(|fields| match device {
+            Device::Name(name) => fields[2] == name,
+            Device::Number(number) => format!("{}:{}", fields[0], fields[1]) == *number,
+        });
+
+        if let Some(fields) = found {
+            let field = |i: usize| fields[i].parse::<u64>().unwrap_or_default();
+            return Ok((
+                fields[2].to_string(),
+                Sample::new([field(3), field(5), field(7), field(9), field(12)]),
+            ));
+        }
+    }
+
+    Err(anyhow!("Device could not be found in '{}'", path.display()))
+}
+
+async fn collect_diskio(
+    s: UnboundedSender<Collection>,
+    item: ConfigItem,
+    root: PathBuf,
+    samples: Samples<5>,
+) -> Result<()> {
+    let value = item.value.as_deref().unwrap_or_default().to_string();
+    let devices = if value.starts_with('/') {
+        mount_devices(&root, &value)?
+    } else {
+        vec![Device::Name(value)]
+    };
+
+    let (device, now) = stats(&root, &devices)?;
+    let last = samples.lock().unwrap().insert(item.name.clone(), now);
+
+    let io = Io::between(last.as_ref(), &now);
+
+    Ok(s.send(Collection::new(
+        item,
+        "{device}: r {read_rate} w {write_rate}",
+        vec![
+            ("device".into(), device.into()),
+            ("read_rate".into(), Value::Rate(io.read_rate)),
+            ("write_rate".into(), Value::Rate(io.write_rate)),
+            ("read_iops".into(), Value::Float(io.read_iops, Some(0))),
+            ("write_iops".into(), Value::Float(io.write_iops, Some(0))),
+            (
+                "iops".into(),
+                Value::Float(io.read_iops + io.write_iops, Some(0)),
+            ),
+            ("busy".into(), Value::Float(io.busy, Some(0))),
+        ],
+        io.busy.floor() as u64,
+    ))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::test::*;
+    use std::time::{Duration, Instant};
+
+    const DISKSTATS: &str = "\
+   8       0 sda 100 0 2000 0 50 0 1000 0 0 500 0
+   8       1 sda1 10 0 200 0 5 0 100 0 0 50 0
+ 253       0 dm-0 1 0 2 0 3 0 4 0 0 5 0
+";
+
+    const MOUNTINFO: &str = "\
+21 1 253:0 / / rw,relatime shared:1 - ext4 /dev/mapper/root rw
+22 21 0:45 / /data rw,relatime shared:2 - btrfs /dev/sda1 rw
+";
+
+    fn sample(at: Instant, counters: [u64; 5]) -> Sample<5> {
+        Sample { at, counters }
+    }
+
+    #[test]
+    fn io_between() {
+        let at = Instant::now();
+        let last = sample(at, [100, 2000, 50, 1000, 500]);
+        let now = sample(at + Duration::from_secs(2), [300, 6000, 150, 3000, 1500]);
+
+        assert_eq!(
+            Io::between(Some(&last), &now),
+            Io {
+                read_rate: 2000.0 * SECTOR_SIZE,
+                write_rate: 1000.0 * SECTOR_SIZE,
+                read_iops: 100.0,
+                write_iops: 50.0,
+                busy: 50.0,
+            }
+        );
+
+        // the first run has nothing to compare with
+        assert_eq!(Io::between(None, &now), Io::default());
+
+        // more time doing I/O than passed, e.g. with requests in flight, is still fully busy
+        let now = sample(at + Duration::from_secs(1), [100, 2000, 50, 1000, 3000]);
+        assert_eq!(Io::between(Some(&last), &now).busy, 100.0);
+    }
+
+    #[test]
+    fn devices() {
+        let root = fake_root("diskio");
+        write(&root, PROC_DISKSTATS, DISKSTATS);
+        write(&root, "proc/self/mountinfo", MOUNTINFO);
+        let device = |value: &str| {
+            let devices = if value.starts_with('/') {
+                mount_devices(&root, value).unwrap()
+            } else {
+                vec![Device::Name(value.to_string())]
+            };
+            stats(&root, &devices).map(|x| x.0).ok()
+        };
+
+        assert_eq!(device("sda").as_deref(), Some("sda"));
+        // by the number of the mount, whatever its source is called
+        assert_eq!(device("/").as_deref(), Some("dm-0"));
+        // btrfs reports a number of its own, so the source device is used
+        assert_eq!(device("/data").as_deref(), Some("sda1"));
+        assert_eq!(device("sdb"), None);
+        assert!(mount_devices(&root, "/missing").is_err());
+    }
+
+    #[tokio::test]
+    async fn collected() {
+        let root = fake_root("diskio-collect");
+        write(&root, PROC_DISKSTATS, DISKSTATS);
+        let collection = collect(
+            &DiskioCollector::new(root),
+            item("name: io\ntype: diskio\nvalue: sda1"),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(rule(&collection, "device"), "sda1");
+        assert_eq!(rule(&collection, "iops"), "0");
+    }
+}
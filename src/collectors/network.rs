@@ -0,0 +1,243 @@
+use super::{read, Collection, Collector, CollectorFuture, Sample, Samples};
+use crate::{config::ConfigItem, formatter::Value};
+use anyhow::{anyhow, Result};
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+// relative to the root of the collector
+const PROC_NET_DEV: &str = "proc/net/dev";
+const PROC_NET_ROUTE: &str = "proc/net/route";
+const SYS_CLASS_NET: &str = "sys/class/net";
+
+// samples are of the bytes received and transmitted
+#[derive(Debug, Clone)]
+pub struct NetworkCollector {
+    root: PathBuf,
+    samples: Samples<2>,
+}
+
+impl Default for NetworkCollector {
+    fn default() -> Self {
+        Self::new("/".into())
+    }
+}
+
+impl NetworkCollector {
+    // root is the directory holding proc and sys, normally /; addresses are always those of
+    // the system
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            samples: Samples::default(),
+        }
+    }
+}
+
+impl Collector for NetworkCollector {
+    fn placeholders(&self) -> Option<&'static [&'static str]> {
+        Some(&[
+            "iface", "state", "rx_rate", "tx_rate", "rx_total", "tx_total", "ip", "ipv6",
+        ])
+    }
+
+    fn collect(&self, s: UnboundedSender<Collection>, item: ConfigItem) -> CollectorFuture {
+        Box::pin(collect_network(
+            s,
+            item,
+            self.root.clone(),
+            self.samples.clone(),
+        ))
+    }
+}
+
+fn default_interface(root: &Path) -> Result<String> {
+    for line in std::fs::read_to_string(root.join(PROC_NET_ROUTE))?
+        .lines()
+        .skip(1)
+    {
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        if fields.len() > 1 && fields[1] == "00000000" {
+            return Ok(fields[0].to_string());
+        }
+    }
+
+    Err(anyhow!("No default route could be found"))
+}
+
+fn counters(root: &Path, iface: &str) -> Result<(u64, u64)> {
+    for line in std::fs::read_to_string(root.join(PROC_NET_DEV))?
+        .lines()
+        .skip(2)
+    {
+        if let Some((name, stats)) = line.split_once(':') {
+            if name.trim() == iface {
+                let stats = stats
+                    .split_whitespace()
+                    .map(|x| x.parse::<u64>().unwrap_or_default())
+                    .collect::<Vec<u64>>();
+                if stats.len() < 9 {
+                    return Err(anyhow!("Malformed statistics for interface '{}'", iface));
+                }
+                return Ok((stats[0], stats[8]));
+            }
+        }
+    }
+
+    Err(anyhow!("Interface '{}' could not be found", iface))
+}
+
+fn sysfs(root: &Path, iface: &str, file: &str) -> Option<String> {
+    read(&root.join(SYS_CLASS_NET).join(iface), file)
+}
+
+fn addresses(iface: &str) -> Vec<IpAddr> {
+    let Ok(addrs) = nix::ifaddrs::getifaddrs() else {
+        return Vec::new();
+    };
+
+    addrs
+        .filter(|x| x.interface_name == iface)
+        .filter_map(|x| x.address)
+        .filter_map(|addr| {
+            addr.as_sockaddr_in()
+                .map(|x| IpAddr::V4(x.ip()))
+                .or_else(|| addr.as_sockaddr_in6().map(|x| IpAddr::V6(x.ip())))
+        })
+        .collect()
+}
+
+async fn collect_network(
+    s: UnboundedSender<Collection>,
+    item: ConfigItem,
+    root: PathBuf,
+    samples: Samples<2>,
+) -> Result<()> {
+    let iface = match &item.value {
+        Some(iface) => iface.to_string(),
+        None => default_interface(&root)?,
+    };
+
+    let (rx, tx) = counters(&root, &iface)?;
+    let now = Sample::new([rx, tx]);
+    let last = samples.lock().unwrap().insert(item.name.clone(), now);
+    let [rx_rate, tx_rate] = now.rates(last.as_ref());
+
+    // speed is reported in Mb/s; it is unavailable for many wireless and virtual devices, in
+    // which case no urgency is reported.
+    let pct = sysfs(&root, &iface, "speed")
+        .and_then(|x| x.parse::<i64>().ok())
+        .filter(|x| *x > 0)
+        .map_or(0, |speed| {
+            (rx_rate.max(tx_rate) / (speed as f64 * 125000.0) * 100.0).floor() as u64
+        });
+
+    let addrs = addresses(&iface);
+    let ipv4 = addrs
+        .iter()
+        .filter(|x| x.is_ipv4())
+        .map(ToString::to_string)
+        .collect::<Vec<String>>();
+    let ipv6 = addrs
+        .iter()
+        .filter(|x| x.is_ipv6())
+        .map(ToString::to_string)
+        .collect::<Vec<String>>();
+
+    Ok(s.send(Collection::new(
+        item,
+        "{iface}: {ip} ↓{rx_rate} ↑{tx_rate}",
+        vec![
+            ("iface".into(), iface.clone().into()),
+            (
+                "state".into(),
+                sysfs(&root, &iface, "operstate").unwrap_or_default().into(),
+            ),
+            ("rx_rate".into(), Value::Rate(rx_rate)),
+            ("tx_rate".into(), Value::Rate(tx_rate)),
+            ("rx_total".into(), Value::Bytes(rx as f64)),
+            ("tx_total".into(), Value::Bytes(tx as f64)),
+            ("ip".into(), ipv4.join(", ").into()),
+            ("ipv6".into(), ipv6.join(", ").into()),
+        ],
+        pct,
+    ))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::test::*;
+
+    const DEV: &str = "Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:     100       1    0    0    0     0          0         0      100       1    0    0    0     0       0          0
+  eth9: RX 0 0 0 0 0 0 0 TX 0 0 0 0 0 0 0 0
+";
+
+    fn root(name: &str, rx: u64, tx: u64) -> PathBuf {
+        let root = fake_root(name);
+        write(
+            &root,
+            PROC_NET_ROUTE,
+            "Iface\tDestination\tGateway\tFlags\nlo\t0000007F\t00000000\t0001\neth9\t00000000\t0100A8C0\t0003\n",
+        );
+        write(
+            &root,
+            PROC_NET_DEV,
+            &DEV.replace("RX", &rx.to_string())
+                .replace("TX", &tx.to_string()),
+        );
+        write(&root, "sys/class/net/eth9/operstate", "up\n");
+        write(&root, "sys/class/net/eth9/speed", "1000\n");
+        root
+    }
+
+    #[tokio::test]
+    async fn default_interface_counters() {
+        let root = root("network", 2000000, 3000000);
+        let collection = collect(
+            &NetworkCollector::new(root),
+            item("name: net\ntype: network"),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(rule(&collection, "iface"), "eth9");
+        assert_eq!(rule(&collection, "state"), "up");
+        assert_eq!(rule(&collection, "rx_total"), "2 MB");
+        assert_eq!(rule(&collection, "tx_total"), "3 MB");
+        // there is no earlier sample to compute a rate from
+        assert_eq!(rule(&collection, "rx_rate"), "0 B/s");
+    }
+
+    #[tokio::test]
+    async fn rates_between_samples() {
+        let collector = NetworkCollector::new(root("network-rates", 0, 0));
+        let item = item("name: net\ntype: network\nvalue: eth9");
+        collect(&collector, item.clone()).await.unwrap();
+
+        // 125MB/s is the speed of the link, so it is fully used
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        root("network-rates", 0, 12500000);
+        let collection = collect(&collector, item).await.unwrap();
+
+        assert!(collection.percent() > 0);
+        assert!(rule(&collection, "tx_rate").ends_with("/s"));
+        assert_eq!(rule(&collection, "rx_rate"), "0 B/s");
+    }
+
+    #[tokio::test]
+    async fn missing_interface() {
+        let err = collect(
+            &NetworkCollector::new(root("network-missing", 0, 0)),
+            item("name: net\ntype: network\nvalue: wlan7"),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(err.to_string(), "Interface 'wlan7' could not be found");
+    }
+}
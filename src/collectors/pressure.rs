@@ -0,0 +1,218 @@
+use super::{validate_format, Collection, Collector, CollectorFuture};
+use crate::{config::ConfigItem, formatter::Value};
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+use tokio::sync::mpsc::UnboundedSender;
+
+pub const PROC_PRESSURE: &str = "/proc/pressure";
+
+const RESOURCES: &[&str] = &["cpu", "memory", "io", "irq"];
+
+// root is the directory holding the pressure of each resource, normally /proc/pressure
+rooted_collector!(PressureCollector, PROC_PRESSURE);
+
+impl Collector for PressureCollector {
+    fn validate(&self, item: &ConfigItem) -> Result<()> {
+        if let Some(value) = item.value.as_deref() {
+            if !value.contains('/') && !RESOURCES.contains(&value) {
+                return Err(anyhow!(
+                    "Pressure block '{}' must be one of {} or the path of a pressure file",
+                    item.name,
+                    RESOURCES.join(", ")
+                ));
+            }
+        }
+
+        validate_format(item)
+    }
+
+    fn placeholders(&self) -> Option<&'static [&'static str]> {
+        Some(&[
+            "resource",
+            "some_avg10",
+            "some_avg60",
+            "some_avg300",
+            "some_total",
+            "full_avg10",
+            "full_avg60",
+            "full_avg300",
+            "full_total",
+        ])
+    }
+
+    fn collect(&self, s: UnboundedSender<Collection>, item: ConfigItem) -> CollectorFuture {
+        Box::pin(collect_pressure(s, item, self.root.clone()))
+    }
+}
+
+// The averages are percents of time stalled over 10, 60 and 300 seconds, and the total is in
+// microseconds.
+#[derive(Debug, Clone, Copy, Default)]
+struct Pressure {
+    avg10: f64,
+    avg60: f64,
+    avg300: f64,
+    total: u64,
+}
+
+impl std::str::FromStr for Pressure {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut res = Self::default();
+
+        for field in s.split_whitespace() {
+            if let Some((key, value)) = field.split_once('=') {
+                match key {
+                    "avg10" => res.avg10 = value.parse()?,
+                    "avg60" => res.avg60 = value.parse()?,
+                    "avg300" => res.avg300 = value.parse()?,
+                    "total" => res.total = value.parse()?,
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+async fn collect_pressure(
+    s: UnboundedSender<Collection>,
+    item: ConfigItem,
+    root: PathBuf,
+) -> Result<()> {
+    let value = item.value.as_deref().unwrap_or("cpu").to_string();
+    let (resource, path) = if value.contains('/') {
+        // a cgroup's pressure file is named after the resource, e.g. memory.pressure
+        let path = PathBuf::from(&value);
+        let resource = path
+            .file_name()
+            .map(|x| {
+                x.to_string_lossy()
+                    .trim_end_matches(".pressure")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        (resource, path)
+    } else {
+        (value.clone(), root.join(&value))
+    };
+
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow!("Could not read pressure from '{}': {}", path.display(), e))?;
+
+    let mut some = None;
+    let mut full = None;
+    for line in content.lines() {
+        match line.split_once(' ') {
+            Some(("some", fields)) => some = Some(fields.parse::<Pressure>()?),
+            Some(("full", fields)) => full = Some(fields.parse::<Pressure>()?),
+            _ => {}
+        }
+    }
+
+    let some = some.ok_or_else(|| anyhow!("No pressure found in '{}'", path.display()))?;
+
+    // full is not reported for the CPU of the whole system on older kernels
+    let mut rules = vec![
+        ("resource".into(), resource.into()),
+        ("some_avg10".into(), Value::Float(some.avg10, Some(2))),
+        ("some_avg60".into(), Value::Float(some.avg60, Some(2))),
+        ("some_avg300".into(), Value::Float(some.avg300, Some(2))),
+        ("some_total".into(), some.total.into()),
+    ];
+    if let Some(full) = full {
+        rules.push(("full_avg10".into(), Value::Float(full.avg10, Some(2))));
+        rules.push(("full_avg60".into(), Value::Float(full.avg60, Some(2))));
+        rules.push(("full_avg300".into(), Value::Float(full.avg300, Some(2))));
+        rules.push(("full_total".into(), full.total.into()));
+    }
+
+    Ok(s.send(Collection::new(
+        item,
+        "{resource}: {some_avg10}%",
+        rules,
+        some.avg10.floor() as u64,
+    ))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::test::*;
+
+    const MEMORY: &str = "\
+some avg10=1.50 avg60=0.75 avg300=0.25 total=12345
+full avg10=0.50 avg60=0.10 avg300=0.00 total=678
+";
+
+    #[tokio::test]
+    async fn resource() {
+        let root = fake_root("pressure");
+        write(&root, "memory", MEMORY);
+
+        let collection = collect(
+            &PressureCollector::new(root),
+            item("name: p\ntype: pressure\nvalue: memory"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(rule(&collection, "resource"), "memory");
+        assert_eq!(rule(&collection, "some_avg10"), "1.50");
+        assert_eq!(rule(&collection, "some_avg300"), "0.25");
+        assert_eq!(rule(&collection, "some_total"), "12345");
+        assert_eq!(rule(&collection, "full_avg10"), "0.50");
+        assert_eq!(rule(&collection, "full_total"), "678");
+        assert_eq!(collection.percent(), 1);
+    }
+
+    #[tokio::test]
+    async fn without_full() {
+        let root = fake_root("pressure-cpu");
+        write(
+            &root,
+            "cpu",
+            "some avg10=25.00 avg60=10.00 avg300=5.00 total=1\n",
+        );
+
+        let collection = collect(
+            &PressureCollector::new(root),
+            item("name: p\ntype: pressure"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(rule(&collection, "resource"), "cpu");
+        assert_eq!(rule(&collection, "some_avg60"), "10.00");
+        assert!(!collection.rules.iter().any(|x| x.0 == "full_avg10"));
+        assert_eq!(collection.percent(), 25);
+    }
+
+    #[tokio::test]
+    async fn cgroup_file() {
+        let root = fake_root("pressure-cgroup");
+        write(&root, "user.slice/io.pressure", MEMORY);
+
+        let yaml = format!(
+            "name: p\ntype: pressure\nvalue: {}",
+            root.join("user.slice/io.pressure").display()
+        );
+        let collection = collect(&PressureCollector::default(), item(&yaml))
+            .await
+            .unwrap();
+        assert_eq!(rule(&collection, "resource"), "io");
+        assert_eq!(rule(&collection, "some_avg60"), "0.75");
+    }
+
+    #[tokio::test]
+    async fn missing_file() {
+        let root = fake_root("pressure-missing");
+
+        assert!(collect(
+            &PressureCollector::new(root),
+            item("name: p\ntype: pressure")
+        )
+        .await
+        .is_err());
+    }
+}
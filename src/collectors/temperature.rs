@@ -0,0 +1,280 @@
+use super::{read, Collection, Collector, CollectorFuture};
+use crate::{config::ConfigItem, formatter::Value};
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc::UnboundedSender;
+
+pub const SYS_CLASS: &str = "/sys/class";
+
+// root is the directory holding hwmon and thermal, normally /sys/class
+rooted_collector!(TemperatureCollector, SYS_CLASS);
+
+impl Collector for TemperatureCollector {
+    fn placeholders(&self) -> Option<&'static [&'static str]> {
+        Some(&["temp", "max", "crit", "fan", "label", "chip"])
+    }
+
+    fn collect(&self, s: UnboundedSender<Collection>, item: ConfigItem) -> CollectorFuture {
+        Box::pin(collect_temperature(s, item, self.root.clone()))
+    }
+}
+
+// temperatures are in degrees celsius
+#[derive(Debug, Clone, Default)]
+struct Sensor {
+    chip: String,
+    label: String,
+    temp: f64,
+    max: Option<f64>,
+    crit: Option<f64>,
+    fan: Option<u64>,
+}
+
+impl Sensor {
+    // value is either a chip name, a sensor label, or both in `chip:label` form
+    fn matches(&self, value: &str) -> bool {
+        let eq = |a: &str, b: &str| a.trim().eq_ignore_ascii_case(b.trim());
+
+        if let Some((chip, label)) = value.split_once(':') {
+            eq(&self.chip, chip) && eq(&self.label, label)
+        } else {
+            eq(&self.chip, value) || eq(&self.label, value)
+        }
+    }
+}
+
+fn read_millis(path: &Path, file: &str) -> Option<f64> {
+    read(path, file)
+        .and_then(|x| x.parse::<f64>().ok())
+        .map(|x| x / 1000.0)
+}
+
+fn entries(path: &Path, prefix: &str) -> Vec<String> {
+    let mut res = std::fs::read_dir(path)
+        .map(|dir| {
+            dir.filter_map(|x| x.ok())
+                .map(|x| x.file_name().to_string_lossy().to_string())
+                .filter(|x| x.starts_with(prefix))
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+
+    // sort numerically so that temp10 follows temp9
+    res.sort_by_key(|x| {
+        let digits = x
+            .trim_start_matches(prefix)
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect::<String>();
+        (digits.parse::<u64>().unwrap_or_default(), x.clone())
+    });
+    res
+}
+
+fn hwmon_sensors(root: &Path) -> Vec<Sensor> {
+    let mut res = Vec::new();
+
+    for hwmon in entries(&root.join("hwmon"), "hwmon") {
+        let path = root.join("hwmon").join(hwmon);
+        let chip = read(&path, "name").unwrap_or_default();
+        let fan = entries(&path, "fan")
+            .iter()
+            .filter(|x| x.ends_with("_input"))
+            .find_map(|x| read(&path, x).and_then(|x| x.parse::<u64>().ok()));
+
+        for input in entries(&path, "temp")
+            .iter()
+            .filter(|x| x.ends_with("_input"))
+        {
+            let sensor = input.trim_end_matches("_input");
+            let label = read(&path, &format!("{}_label", sensor));
+            if let Some(temp) = read_millis(&path, input) {
+                res.push(Sensor {
+                    chip: chip.clone(),
+                    label: label.unwrap_or(sensor.to_string()),
+                    temp,
+                    max: read_millis(&path, &format!("{}_max", sensor)),
+                    crit: read_millis(&path, &format!("{}_crit", sensor)),
+                    fan,
+                });
+            }
+        }
+    }
+
+    res
+}
+
+fn thermal_sensors(root: &Path) -> Vec<Sensor> {
+    let mut res = Vec::new();
+
+    for zone in entries(&root.join("thermal"), "thermal_zone") {
+        let path = root.join("thermal").join(&zone);
+        if let Some(temp) = read_millis(&path, "temp") {
+            let mut crit = None;
+            let mut max = None;
+
+            for trip in entries(&path, "trip_point_")
+                .iter()
+                .filter(|x| x.ends_with("_type"))
+            {
+                let temp = read_millis(&path, &trip.replace("_type", "_temp"));
+                match read(&path, trip).as_deref() {
+                    Some("critical") => crit = temp,
+                    Some("hot") => max = temp,
+                    _ => {}
+                }
+            }
+
+            res.push(Sensor {
+                chip: read(&path, "type").unwrap_or_default(),
+                label: zone,
+                temp,
+                max,
+                crit,
+                fan: None,
+            });
+        }
+    }
+
+    res
+}
+
+async fn collect_temperature(
+    s: UnboundedSender<Collection>,
+    item: ConfigItem,
+    root: PathBuf,
+) -> Result<()> {
+    let mut sensors = hwmon_sensors(&root);
+    sensors.append(&mut thermal_sensors(&root));
+
+    // fans are commonly on a different chip than the sensor, so fall back to the first one found
+    let any_fan = sensors.iter().find_map(|x| x.fan);
+
+    let sensor = if let Some(value) = &item.value {
+        sensors
+            .into_iter()
+            .find(|x| x.matches(value))
+            .ok_or_else(|| anyhow!("Temperature sensor '{}' could not be found", value))?
+    } else {
+        sensors
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("No temperature sensors could be found"))?
+    };
+
+    // without a critical temperature, the degrees are used as the percent
+    let pct = match sensor.crit {
+        Some(crit) if crit > 0.0 => sensor.temp / crit * 100.0,
+        _ => sensor.temp,
+    }
+    .max(0.0)
+    .floor() as u64;
+
+    let degrees = |x: f64| Value::Float(x, Some(0));
+    let mut rules = vec![
+        ("temp".into(), degrees(sensor.temp)),
+        ("label".into(), sensor.label.into()),
+        ("chip".into(), sensor.chip.into()),
+    ];
+
+    if let Some(max) = sensor.max {
+        rules.push(("max".into(), degrees(max)));
+    }
+
+    if let Some(crit) = sensor.crit {
+        rules.push(("crit".into(), degrees(crit)));
+    }
+
+    if let Some(fan) = sensor.fan.or(any_fan) {
+        rules.push(("fan".into(), fan.into()));
+    }
+
+    Ok(s.send(Collection::new(item, "{label}: {temp}°C", rules, pct))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::test::*;
+
+    fn root(name: &str) -> PathBuf {
+        let root = fake_root(name);
+        write(&root, "hwmon/hwmon0/name", "coretemp\n");
+        write(&root, "hwmon/hwmon0/temp1_input", "45000\n");
+        write(&root, "hwmon/hwmon0/temp1_label", "Package id 0\n");
+        write(&root, "hwmon/hwmon0/temp1_max", "80000\n");
+        write(&root, "hwmon/hwmon0/temp1_crit", "100000\n");
+        write(&root, "hwmon/hwmon0/temp10_input", "60000\n");
+        write(&root, "hwmon/hwmon1/name", "nct6775\n");
+        write(&root, "hwmon/hwmon1/fan2_input", "1200\n");
+        write(&root, "hwmon/hwmon1/temp1_input", "30000\n");
+        write(&root, "thermal/thermal_zone0/type", "acpitz\n");
+        write(&root, "thermal/thermal_zone0/temp", "52500\n");
+        write(
+            &root,
+            "thermal/thermal_zone0/trip_point_0_type",
+            "critical\n",
+        );
+        write(&root, "thermal/thermal_zone0/trip_point_0_temp", "105000\n");
+        root
+    }
+
+    async fn collect_root(root: PathBuf, yaml: &str) -> Result<Collection> {
+        collect(&TemperatureCollector::new(root), item(yaml)).await
+    }
+
+    #[tokio::test]
+    async fn first_sensor() {
+        let collection = collect_root(root("temperature"), "name: t\ntype: temperature")
+            .await
+            .unwrap();
+
+        assert_eq!(rule(&collection, "label"), "Package id 0");
+        assert_eq!(rule(&collection, "chip"), "coretemp");
+        assert_eq!(rule(&collection, "temp"), "45");
+        assert_eq!(rule(&collection, "max"), "80");
+        assert_eq!(rule(&collection, "crit"), "100");
+        // the fan is on another chip
+        assert_eq!(rule(&collection, "fan"), "1200");
+        assert_eq!(collection.percent(), 45);
+    }
+
+    #[tokio::test]
+    async fn sensor_by_name() {
+        let root = root("temperature-name");
+
+        // temp10 is sorted after temp1, and is labelled by its file without a label
+        let collection = collect_root(root.clone(), "name: t\ntype: temperature\nvalue: temp10")
+            .await
+            .unwrap();
+        assert_eq!(rule(&collection, "temp"), "60");
+        // without a critical temperature, the degrees are the percent
+        assert_eq!(collection.percent(), 60);
+
+        let collection = collect_root(root, "name: t\ntype: temperature\nvalue: ACPITZ")
+            .await
+            .unwrap();
+        assert_eq!(rule(&collection, "label"), "thermal_zone0");
+        assert_eq!(rule(&collection, "crit"), "105");
+        assert_eq!(collection.percent(), 50);
+    }
+
+    #[tokio::test]
+    async fn missing_sensor() {
+        let err = collect_root(
+            root("temperature-missing"),
+            "name: t\ntype: temperature\nvalue: coretemp:Core 9",
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Temperature sensor 'coretemp:Core 9' could not be found"
+        );
+
+        let err = collect_root(fake_root("temperature-none"), "name: t\ntype: temperature")
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "No temperature sensors could be found");
+    }
+}
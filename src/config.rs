@@ -1,13 +1,14 @@
-use crate::collectors::*;
+use crate::{bar::Click, collectors::*, formatter::Template};
 use anyhow::{anyhow, Result};
 use chrono::Duration;
 use fancy_duration::FancyDuration;
 use serde_derive::{Deserialize, Serialize};
-use std::sync::Arc;
-use tokio::sync::{
-    mpsc::{UnboundedReceiver, UnboundedSender},
-    Mutex,
+use std::{
+    collections::{hash_map::RandomState, BTreeMap},
+    hash::{BuildHasher, Hasher},
+    sync::Arc,
 };
+use tokio::sync::mpsc::UnboundedSender;
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
@@ -18,28 +19,153 @@ pub struct Config {
 impl Config {
     pub fn load(filename: std::path::PathBuf) -> Result<Self> {
         if !filename.exists() {
-            return Err(anyhow!("Config file '{}' does not exist", filename.display()));
+            return Err(anyhow!(
+                "Config file '{}' does not exist",
+                filename.display()
+            ));
         }
         let mut io = std::fs::OpenOptions::new();
         io.read(true);
         let r = io.open(filename)?;
-        Ok(serde_yaml::from_reader(r)?)
+        let mut config: Self = serde_yaml::from_reader(r)?;
+
+        for (i, page) in config.pages.iter_mut().enumerate() {
+            for item in &mut page.0 {
+                item.page = i;
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn validate(&self, registry: &Registry) -> Result<()> {
+        // blocks are told apart by their names, in the bar, its clicks and their results
+        let mut seen = std::collections::BTreeSet::new();
+        for page in &self.pages {
+            for item in &page.0 {
+                if !seen.insert(item.name.as_str()) {
+                    return Err(anyhow!("block name '{}' is used more than once", item.name));
+                }
+
+                item.validate(registry)?;
+            }
+        }
+
+        Ok(())
     }
 
     pub async fn launch_collectors(
         &mut self,
+        registry: &Registry,
         s: UnboundedSender<Collection>,
-        result: UnboundedSender<Result<()>>,
-        commands: Arc<Mutex<UnboundedReceiver<CommandItem>>>,
+        result: UnboundedSender<CollectorResult>,
     ) -> Result<()> {
+        let interval = self.update_interval();
         for page in &mut self.pages {
-            page.launch_collectors(s.clone(), result.clone(), commands.clone())
+            page.launch_collectors(registry, interval, s.clone(), result.clone())
                 .await?;
         }
 
         Ok(())
     }
 
+    // the earliest time a collector is due, not counting those still running
+    pub fn next_update(&self) -> Option<chrono::DateTime<chrono::Local>> {
+        self.pages
+            .iter()
+            .flat_map(|page| page.0.iter())
+            .filter(|item| !item.running)
+            .map(|item| item.next_update)
+            .min()
+    }
+
+    // replaces the configuration, keeping the update times of blocks that remain. Persistent
+    // blocks are stopped and started again, so they run with the new configuration.
+    pub fn reloaded(&mut self, mut new: Config) {
+        for item in self.pages.iter().flat_map(|page| page.0.iter()) {
+            if let Some(task) = item.task.as_ref().filter(|_| item.persistent()) {
+                task.abort();
+            }
+        }
+
+        for item in new.pages.iter_mut().flat_map(|page| page.0.iter_mut()) {
+            if let Some(old) = self.item(&item.name).filter(|x| !x.persistent()) {
+                item.last_updated = old.last_updated;
+                item.next_update = old.next_update;
+                item.failures = old.failures;
+                item.running = old.running;
+            }
+        }
+
+        *self = new;
+    }
+
+    // runs the clicked block again straight away, or once it finishes if it is running
+    pub fn clicked(&mut self, click: Click) {
+        if let Some(item) = self
+            .pages
+            .iter_mut()
+            .flat_map(|page| page.0.iter_mut())
+            .find(|item| item.name == click.name)
+        {
+            item.next_update = chrono::Local::now();
+            item.click = Some(click);
+        }
+    }
+
+    // tracks failing collectors, so they are retried with backoff
+    pub fn record_result(&mut self, name: &str, result: Result<()>) {
+        let interval = self.update_interval();
+        if let Some(item) = self
+            .pages
+            .iter_mut()
+            .flat_map(|page| page.0.iter_mut())
+            .find(|item| item.name == name)
+        {
+            item.running = false;
+
+            // persistent blocks run for as long as their command does, so they are due again
+            // counting from when they finished rather than from when they started
+            let finished = chrono::Local::now();
+            let start = if item.persistent() {
+                finished
+            } else {
+                item.last_updated
+            };
+
+            match result {
+                Ok(_) => {
+                    item.failures = 0;
+                    if item.persistent() {
+                        item.next_update = item.schedule(
+                            finished,
+                            item.interval(interval),
+                            item.align.unwrap_or(false),
+                        );
+                    }
+                }
+                Err(e) => {
+                    item.failures += 1;
+                    item.next_update = start + item.retry_interval() + item.jitter();
+                    eprintln!(
+                        "Block '{}' failed: {}; retrying in {}",
+                        name,
+                        e,
+                        FancyDuration(item.retry_interval()).to_string()
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn item(&self, name: &str) -> Option<ConfigItem> {
+        self.pages
+            .iter()
+            .flat_map(|page| page.0.iter())
+            .find(|item| item.name == name)
+            .cloned()
+    }
+
     pub fn pages(&self) -> Vec<ConfigPage> {
         self.pages.clone()
     }
@@ -62,12 +188,13 @@ impl ConfigPage {
 
     pub async fn launch_collectors(
         &mut self,
+        registry: &Registry,
+        interval: Duration,
         s: UnboundedSender<Collection>,
-        result: UnboundedSender<Result<()>>,
-        commands: Arc<Mutex<UnboundedReceiver<CommandItem>>>,
+        result: UnboundedSender<CollectorResult>,
     ) -> Result<()> {
         for item in &mut self.0 {
-            item.launch_collector(s.clone(), result.clone(), commands.clone())
+            item.launch_collector(registry, interval, s.clone(), result.clone())
                 .await?;
         }
 
@@ -75,130 +202,676 @@ impl ConfigPage {
     }
 }
 
-// every edit to this must mirror a CollectionType
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub enum ModuleType {
-    #[serde(rename = "static")]
-    Static,
-    #[serde(rename = "dynamic")]
-    Dynamic,
-    #[serde(rename = "cpu")]
-    CPU,
-    #[serde(rename = "disk", alias = "hdd")]
-    Disk,
-    #[serde(rename = "memory", alias = "ram")]
-    Memory,
-    #[serde(rename = "load", alias = "load_average")]
-    Load,
-    #[serde(rename = "time", alias = "clock")]
-    #[default]
-    Time,
-    #[serde(rename = "music")]
-    Music,
-    #[serde(rename = "command")]
-    Command,
-}
-
-impl From<CollectionType> for ModuleType {
-    fn from(value: CollectionType) -> Self {
-        match value {
-            CollectionType::Static => Self::Static,
-            CollectionType::Dynamic(..) => Self::Dynamic,
-            CollectionType::CPU { .. } => Self::CPU,
-            CollectionType::Disk { .. } => Self::Disk,
-            CollectionType::Memory { .. } => Self::Memory,
-            CollectionType::Load(..) => Self::Load,
-            CollectionType::Time(..) => Self::Time,
-            CollectionType::Music { .. } => Self::Music,
-            CollectionType::Command(..) => Self::Command,
-        }
-    }
-}
-
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CommandItem {
     pub name: String,
+    #[serde(default)]
     pub value: String,
     pub percent: Option<u64>,
+    // how long the block is shown for, after which it is removed, or greyed out if stale is set
+    pub ttl: Option<FancyDuration<Duration>>,
+    pub stale: Option<bool>,
+    // removes the block from the bar until it is written again
+    pub delete: Option<bool>,
+    // any other fields of the i3bar protocol, e.g. color or background
+    #[serde(flatten)]
+    pub block: crate::bar::Block,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ConfigItem {
     pub name: String,
     #[serde(rename = "type")]
-    pub typ: ModuleType,
-    pub value: Option<String>,
+    pub typ: String,
+    #[serde(default)]
+    pub value: Option<BlockValue>,
     pub format: Option<String>,
     pub urgency: Option<(u8, u8, u8)>,
     pub urgency_colors: Option<(String, String, String)>,
     pub icon: Option<String>,
     pub update_interval: Option<FancyDuration<Duration>>,
+    pub on_click: Option<BTreeMap<String, ClickAction>>,
+    pub error_format: Option<String>,
+    pub error_color: Option<String>,
+    pub timeout: Option<FancyDuration<Duration>>,
+    pub align: Option<bool>,
+    pub markup: Option<String>,
+    pub jitter: Option<FancyDuration<Duration>>,
+    // the directory and environment commands of the block run with, both its own if it is a
+    // command block and those run when it is clicked
+    pub cwd: Option<String>,
+    pub env: Option<BTreeMap<String, String>>,
+    // any other keys, which are options for the block's collector to read, e.g. the mounts of a
+    // disk block
+    #[serde(flatten)]
+    pub options: serde_yaml::Mapping,
 
     #[serde(skip)]
     pub(crate) last_updated: chrono::DateTime<chrono::Local>,
+    #[serde(skip)]
+    pub(crate) next_update: chrono::DateTime<chrono::Local>,
+    // consecutive failures of the collector
+    #[serde(skip)]
+    pub(crate) failures: u32,
+    // set while the collector runs, so that only one runs at a time
+    #[serde(skip)]
+    pub(crate) running: bool,
+    // the running collector, so a persistent one can be stopped when the block is reloaded
+    #[serde(skip)]
+    pub(crate) task: Option<tokio::task::AbortHandle>,
+    // the index of the page the block is on
+    #[serde(skip)]
+    pub(crate) page: usize,
+    // the click an i3blocks script is run again for, which it is given like i3blocks does
+    #[serde(skip)]
+    pub(crate) click: Option<Click>,
+}
+
+// A value is text, but a command can also be given as a list of arguments. The list is kept as
+// a string quoted so that it splits back into the same arguments, without expanding anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockValue {
+    text: String,
+    list: bool,
+}
+
+impl BlockValue {
+    pub fn is_list(&self) -> bool {
+        self.list
+    }
+}
+
+impl From<String> for BlockValue {
+    fn from(text: String) -> Self {
+        Self { text, list: false }
+    }
+}
+
+impl std::ops::Deref for BlockValue {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl std::fmt::Display for BlockValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl serde::Serialize for BlockValue {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.text)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for BlockValue {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct ValueVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for ValueVisitor {
+            type Value = BlockValue;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a string or a list of arguments")
+            }
+
+            fn visit_bool<E: serde::de::Error>(self, v: bool) -> Result<Self::Value, E> {
+                Ok(v.to_string().into())
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(v.to_string().into())
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(v.to_string().into())
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                Ok(v.to_string().into())
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(v.to_string().into())
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut args = Vec::new();
+                while let Some(arg) = seq.next_element::<String>()? {
+                    args.push(command::quote(&arg));
+                }
+                Ok(BlockValue {
+                    text: args.join(" "),
+                    list: true,
+                })
+            }
+        }
+
+        d.deserialize_any(ValueVisitor)
+    }
+}
+
+// Every field that is set is acted on when the button is clicked.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClickAction {
+    pub command: Option<String>,
+    pub formats: Option<Vec<String>>,
+    pub page: Option<String>,
+    pub message: Option<CommandItem>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageChange {
+    Next,
+    Previous,
+    Page(usize),
+}
+
+impl ClickAction {
+    pub fn page_change(&self) -> Result<Option<PageChange>> {
+        Ok(match self.page.as_deref() {
+            None => None,
+            Some("next" | "up") => Some(PageChange::Next),
+            Some("previous" | "prev" | "down") => Some(PageChange::Previous),
+            Some(page) => Some(PageChange::Page(page.parse().map_err(|_| {
+                anyhow!(
+                    "Page '{}' must be 'next', 'previous' or a page number",
+                    page
+                )
+            })?)),
+        })
+    }
+}
+
+// A button and modifiers in `modifier+modifier+button` form, e.g. `shift+left`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClickBinding {
+    pub button: u16,
+    pub modifiers: Vec<String>,
+}
+
+// Lock and Mod2 are caps and num lock, which should not change what a click does.
+const IGNORED_MODIFIERS: &[&str] = &["Lock", "Mod2"];
+
+impl std::str::FromStr for ClickBinding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split('+').map(str::trim).collect::<Vec<&str>>();
+        let button = parts.pop().unwrap_or_default();
+        let button = match button.to_lowercase().as_str() {
+            "left" => 1,
+            "middle" => 2,
+            "right" => 3,
+            "scroll-up" | "scroll_up" | "wheel-up" => 4,
+            "scroll-down" | "scroll_down" | "wheel-down" => 5,
+            "scroll-left" | "scroll_left" => 6,
+            "scroll-right" | "scroll_right" => 7,
+            other => other
+                .parse()
+                .map_err(|_| anyhow!("Unknown mouse button '{}' in '{}'", button, s))?,
+        };
+
+        let mut modifiers = Vec::new();
+        for modifier in parts {
+            modifiers.push(
+                match modifier.to_lowercase().as_str() {
+                    "shift" => "Shift",
+                    "ctrl" | "control" => "Control",
+                    "alt" | "mod1" => "Mod1",
+                    "mod3" => "Mod3",
+                    "super" | "mod4" => "Mod4",
+                    "mod5" => "Mod5",
+                    _ => return Err(anyhow!("Unknown modifier '{}' in '{}'", modifier, s)),
+                }
+                .to_string(),
+            );
+        }
+        modifiers.sort();
+
+        Ok(Self { button, modifiers })
+    }
+}
+
+impl ClickBinding {
+    pub fn matches(&self, click: &Click) -> bool {
+        let mut modifiers = click
+            .modifiers
+            .iter()
+            .filter(|x| !IGNORED_MODIFIERS.contains(&x.as_str()))
+            .cloned()
+            .collect::<Vec<String>>();
+        modifiers.sort();
+
+        self.button == click.button && self.modifiers == modifiers
+    }
 }
 
+// failed retries are delayed up to this long, or the update interval if that is longer
+const MAX_RETRY_INTERVAL: i64 = 300;
+
+// collectors are not run more often than this many milliseconds
+const MIN_UPDATE_INTERVAL: i64 = 100;
+
+// collectors are stopped after running this long, unless the block sets a timeout
+const DEFAULT_TIMEOUT: i64 = 10;
+
 async fn spawn(
-    s: UnboundedSender<Result<()>>,
-    f: impl std::future::Future<Output = Result<()>> + Send + 'static,
-) -> Result<()> {
-    Ok(s.send(tokio::spawn(f).await?)?)
+    item: ConfigItem,
+    s: UnboundedSender<Collection>,
+    result: UnboundedSender<CollectorResult>,
+    handle: tokio::task::JoinHandle<Result<()>>,
+) {
+    let timeout = item.timeout();
+    let abort = handle.abort_handle();
+
+    // aborting drops the collector, which kills any command it is waiting for. Persistent
+    // collectors run for as long as their command does.
+    let res = if item.persistent() {
+        handle.await
+    } else {
+        match tokio::time::timeout(timeout.to_std().unwrap_or_default(), handle).await {
+            Ok(res) => res,
+            Err(_) => {
+                abort.abort();
+                Ok(Err(anyhow!(
+                    "timed out after {}",
+                    FancyDuration(timeout).to_string()
+                )))
+            }
+        }
+    };
+
+    let res = match res {
+        Ok(res) => res,
+        // stopped by a reload, which starts the block again
+        Err(e) if e.is_cancelled() => return,
+        Err(e) => Err(anyhow!("collector stopped: {}", e)),
+    };
+
+    match &res {
+        Err(e) => {
+            let _ = s.send(Collection::error(item.clone(), &e.to_string()));
+        }
+        Ok(_) if item.failures > 0 => {
+            let _ = s.send(Collection::cleared(item.clone()));
+        }
+        Ok(_) => {}
+    }
+
+    let _ = result.send((item.name, res));
 }
 
 impl ConfigItem {
+    pub fn collector(&self, registry: &Registry) -> Result<Arc<dyn Collector>> {
+        registry
+            .get(&self.typ)
+            .ok_or_else(|| anyhow!("Block '{}' has unknown type '{}'", self.name, self.typ))
+    }
+
+    pub fn validate(&self, registry: &Registry) -> Result<()> {
+        for (binding, action) in self.on_click.iter().flatten() {
+            binding
+                .parse::<ClickBinding>()
+                .and_then(|_| action.page_change())
+                .map_err(|e| anyhow!("Block '{}': {}", self.name, e))?;
+        }
+
+        // clicks cycle through a single list of formats, so only one binding may have them
+        if self
+            .on_click
+            .iter()
+            .flatten()
+            .filter(|(_, action)| action.formats.is_some())
+            .count()
+            > 1
+        {
+            return Err(anyhow!(
+                "Block '{}' may only have formats on one click binding",
+                self.name
+            ));
+        }
+
+        if let Some(format) = &self.error_format {
+            Template::parse(format).map_err(|e| anyhow!("Block '{}': {}", self.name, e))?;
+        }
+
+        let collector = self.collector(registry)?;
+        if self.option::<bool>("persist")?.unwrap_or(false) && !collector.persists() {
+            return Err(anyhow!(
+                "Block '{}' cannot persist, as '{}' blocks are collected one update at a time",
+                self.name,
+                self.typ
+            ));
+        }
+
+        // collectors explain themselves first, e.g. disk blocks take a list as mounts instead
+        collector.validate(self)?;
+
+        if self.value.as_ref().is_some_and(|x| x.is_list()) && !collector.arguments() {
+            return Err(anyhow!(
+                "Block '{}' has a list as its value, which only command blocks take",
+                self.name
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn click_action(&self, click: &Click) -> Option<ClickAction> {
+        self.on_click
+            .iter()
+            .flatten()
+            .find_map(|(binding, action)| {
+                binding
+                    .parse::<ClickBinding>()
+                    .is_ok_and(|x| x.matches(click))
+                    .then(|| action.clone())
+            })
+    }
+
+    pub fn urgency_color(&self, pct: u64) -> Option<String> {
+        let (Some(colors), Some(urgency)) = (&self.urgency_colors, self.urgency) else {
+            return None;
+        };
+
+        if pct > urgency.2.into() {
+            Some(colors.2.clone())
+        } else if pct > urgency.1.into() {
+            Some(colors.1.clone())
+        } else if pct > urgency.0.into() {
+            Some(colors.0.clone())
+        } else {
+            None
+        }
+    }
+
+    // formats to cycle through after the block's own format
+    pub fn formats(&self) -> Vec<String> {
+        self.on_click
+            .iter()
+            .flatten()
+            .find_map(|(_, action)| action.formats.clone())
+            .unwrap_or_default()
+    }
+
+    fn update_interval(&self) -> Duration {
+        self.update_interval
+            .clone()
+            .map_or(Duration::zero(), |x| x.duration())
+    }
+
+    // Reads an option of the block, failing if it is not of the type the collector expects.
+    // Collectors check their options in validate, so they are known to be valid when collecting.
+    pub fn option<T: serde::de::DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        self.options
+            .get(key)
+            .map(|value| {
+                serde_yaml::from_value(value.clone())
+                    .map_err(|e| anyhow!("Block '{}': invalid {}: {}", self.name, key, e))
+            })
+            .transpose()
+    }
+
+    pub fn persistent(&self) -> bool {
+        self.option("persist").ok().flatten().unwrap_or(false)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+            .clone()
+            .map_or(Duration::seconds(DEFAULT_TIMEOUT), |x| x.duration())
+    }
+
+    // doubles with every consecutive failure, starting from the update interval or a second
+    pub fn retry_interval(&self) -> Duration {
+        let interval = self.update_interval().max(Duration::seconds(1));
+        let max = interval.max(Duration::seconds(MAX_RETRY_INTERVAL));
+        let factor = 1 << self.failures.saturating_sub(1).min(16);
+
+        (interval * factor).min(max)
+    }
+
+    // a random delay of up to the jitter, so that blocks with the same interval spread out
+    fn jitter(&self) -> Duration {
+        let jitter = self
+            .jitter
+            .clone()
+            .map_or(0, |x| x.duration().num_milliseconds());
+        if jitter <= 0 {
+            return Duration::zero();
+        }
+
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write(self.name.as_bytes());
+        Duration::milliseconds((hasher.finish() % jitter as u64) as i64)
+    }
+
+    // the block's own update interval, or the default when it does not set one
+    fn interval(&self, default: Duration) -> Duration {
+        self.update_interval
+            .clone()
+            .map_or(default, |x| x.duration())
+            .max(Duration::milliseconds(MIN_UPDATE_INTERVAL))
+    }
+
+    // the next multiple of the interval on the local clock when aligned, otherwise one interval
+    // from now
+    fn schedule<Tz: chrono::TimeZone>(
+        &self,
+        now: chrono::DateTime<Tz>,
+        interval: Duration,
+        aligned: bool,
+    ) -> chrono::DateTime<Tz> {
+        use chrono::Offset;
+
+        let step = interval.num_nanoseconds().unwrap_or_default();
+        let next = if aligned && step > 0 {
+            let local = now.timestamp_nanos_opt().unwrap_or_default()
+                + now.offset().fix().local_minus_utc() as i64 * 1_000_000_000;
+            now + Duration::nanoseconds(step - local.rem_euclid(step))
+        } else {
+            now + interval
+        };
+
+        next + self.jitter()
+    }
+
+    // interval is used when the block does not set its own
     pub async fn launch_collector(
         &mut self,
+        registry: &Registry,
+        interval: Duration,
         s: UnboundedSender<Collection>,
-        result: UnboundedSender<Result<()>>,
-        commands: Arc<Mutex<UnboundedReceiver<CommandItem>>>,
+        result: UnboundedSender<CollectorResult>,
     ) -> Result<()> {
-        if (self.update_interval.is_some()
-            && self.last_updated + self.update_interval.clone().unwrap().duration()
-                < chrono::Local::now())
-            || self.update_interval.is_none()
-        {
-            let clone = self.clone();
-
-            match self.typ {
-                ModuleType::Static => {
-                    if self.value.is_some() {
-                        tokio::spawn(spawn(result, collect_static(s, clone)));
-                    } else {
-                        return Err(anyhow!(
-                            "Static block '{}' must have a value",
-                            self.clone().name
-                        ));
-                    }
-                }
-                ModuleType::Dynamic => {
-                    tokio::spawn(spawn(result, collect_dynamic(s, clone, commands.clone())));
-                }
-                ModuleType::Time => {
-                    tokio::spawn(spawn(result, collect_time(s, clone)));
-                }
-                ModuleType::Load => {
-                    tokio::spawn(spawn(result, collect_load(s, clone)));
-                }
-                ModuleType::CPU => {
-                    tokio::spawn(spawn(result, collect_cpu(s, clone)));
-                }
-                ModuleType::Memory => {
-                    tokio::spawn(spawn(result, collect_memory(s, clone)));
-                }
-                ModuleType::Disk => {
-                    tokio::spawn(spawn(result, collect_disk(s, clone)));
-                }
-                ModuleType::Music => {
-                    tokio::spawn(spawn(result, collect_music(s, clone)));
-                }
-                ModuleType::Command => {
-                    tokio::spawn(spawn(result, collect_command(s, clone)));
-                }
-            }
+        let now = chrono::Local::now();
+
+        if !self.running && self.next_update <= now {
+            // blocks are validated when the configuration is loaded, not on every update
+            let collector = self.collector(registry)?;
 
-            self.last_updated = chrono::Local::now();
+            let handle = tokio::spawn(collector.collect(s.clone(), self.clone()));
+            self.task = Some(handle.abort_handle());
+            tokio::spawn(spawn(self.clone(), s, result, handle));
+
+            let interval = self.interval(interval);
+            let aligned = self.align.unwrap_or(collector.aligned());
+
+            self.running = true;
+            self.click = None;
+            self.last_updated = now;
+            self.next_update = self.schedule(now, interval, aligned);
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> Registry {
+        let (_, commands) = tokio::sync::mpsc::unbounded_channel();
+        Registry::new(Arc::new(tokio::sync::Mutex::new(commands)))
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let config: Config = serde_yaml::from_str(
+            "pages:\n- - name: a\n    type: load\n- - name: a\n    type: cpu\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.validate(&registry()).unwrap_err().to_string(),
+            "block name 'a' is used more than once"
+        );
+
+        let config: Config = serde_yaml::from_str(
+            "pages:\n- - name: a\n    type: load\n- - name: b\n    type: cpu\n",
+        )
+        .unwrap();
+        assert!(config.validate(&registry()).is_ok());
+    }
+
+    #[test]
+    fn value_lists_are_for_commands() {
+        let validate = |yaml: &str| {
+            crate::collectors::test::item(yaml)
+                .validate(&registry())
+                .map_err(|e| e.to_string())
+        };
+
+        assert!(validate("name: c\ntype: command\nvalue: [echo, a b]").is_ok());
+        assert_eq!(
+            validate("name: s\ntype: static\nvalue: [a, b]"),
+            Err("Block 's' has a list as its value, which only command blocks take".to_string())
+        );
+        assert_eq!(
+            validate("name: d\ntype: disk\nvalue: [/, /home]"),
+            Err(
+                "Disk block 'd' takes a single mount point as its value; list several in mounts"
+                    .to_string()
+            )
+        );
+        assert!(validate("name: s\ntype: static\nvalue: ~").is_err());
+    }
+
+    fn local(time: &str) -> chrono::DateTime<chrono::FixedOffset> {
+        chrono::DateTime::parse_from_rfc3339(time).unwrap()
+    }
+
+    #[test]
+    fn schedule_aligned() {
+        let item = crate::collectors::test::item("name: t\ntype: time");
+
+        // the next second, minute or hour on the clock of the offset, not of UTC
+        assert_eq!(
+            item.schedule(
+                local("2024-05-01T10:20:30.250+05:30"),
+                Duration::seconds(1),
+                true
+            ),
+            local("2024-05-01T10:20:31+05:30")
+        );
+        assert_eq!(
+            item.schedule(
+                local("2024-05-01T10:20:30+05:30"),
+                Duration::minutes(1),
+                true
+            ),
+            local("2024-05-01T10:21:00+05:30")
+        );
+        assert_eq!(
+            item.schedule(local("2024-05-01T10:20:30+05:30"), Duration::hours(1), true),
+            local("2024-05-01T11:00:00+05:30")
+        );
+        assert_eq!(
+            item.schedule(
+                local("2024-05-01T10:20:30-09:45"),
+                Duration::minutes(15),
+                true
+            ),
+            local("2024-05-01T10:30:00-09:45")
+        );
+        // on a boundary, the next one
+        assert_eq!(
+            item.schedule(
+                local("2024-05-01T10:21:00+05:30"),
+                Duration::minutes(1),
+                true
+            ),
+            local("2024-05-01T10:22:00+05:30")
+        );
+
+        assert_eq!(
+            item.schedule(
+                local("2024-05-01T10:20:30.250+05:30"),
+                Duration::minutes(1),
+                false
+            ),
+            local("2024-05-01T10:21:30.250+05:30")
+        );
+    }
+
+    #[test]
+    fn minimum_interval() {
+        let item = crate::collectors::test::item("name: t\ntype: load\nupdate_interval: 1ms");
+        assert_eq!(
+            item.interval(Duration::seconds(5)),
+            Duration::milliseconds(100)
+        );
+
+        let item = crate::collectors::test::item("name: t\ntype: load");
+        assert_eq!(item.interval(Duration::seconds(5)), Duration::seconds(5));
+        assert_eq!(item.interval(Duration::zero()), Duration::milliseconds(100));
+    }
+
+    #[test]
+    fn retry_backoff() {
+        let mut item = crate::collectors::test::item("name: t\ntype: load");
+        let retries = |item: &mut ConfigItem| {
+            (1..=12)
+                .map(|failures| {
+                    item.failures = failures;
+                    item.retry_interval().num_seconds()
+                })
+                .collect::<Vec<i64>>()
+        };
+
+        // doubling from a second, up to five minutes
+        assert_eq!(
+            retries(&mut item),
+            vec![1, 2, 4, 8, 16, 32, 64, 128, 256, 300, 300, 300]
+        );
+
+        // from the update interval, and up to it when it is longer than five minutes
+        item.update_interval = Some(FancyDuration(Duration::seconds(100)));
+        assert_eq!(retries(&mut item)[..4], [100, 200, 300, 300]);
+        item.update_interval = Some(FancyDuration(Duration::minutes(10)));
+        assert_eq!(retries(&mut item)[..2], [600, 600]);
+
+        // many failures do not overflow
+        item.failures = u32::MAX;
+        assert_eq!(item.retry_interval(), Duration::minutes(10));
+    }
+
+    #[test]
+    fn jitter() {
+        let mut item = crate::collectors::test::item("name: t\ntype: load");
+        assert_eq!(item.jitter(), Duration::zero());
+
+        item.jitter = Some(FancyDuration(Duration::seconds(2)));
+        for _ in 0..100 {
+            let jitter = item.jitter();
+            assert!(jitter >= Duration::zero() && jitter < Duration::seconds(2));
+        }
+    }
+}
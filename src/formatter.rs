@@ -1,4 +1,642 @@
-pub type Rules<'a> = Vec<(&'a str, String)>;
+use anyhow::{anyhow, Result};
+use pretty_bytes::converter::convert;
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+// values by the name of their placeholder, which is usually a literal but may be built, e.g. for
+// each core of a CPU
+pub type Rules<'a> = Vec<(Cow<'a, str>, Value)>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Integer(i64),
+    // the precision is the default used when the format does not specify one
+    Float(f64, Option<usize>),
+    Bytes(f64),
+    // bytes per second
+    Rate(f64),
+    // percentages, oldest first
+    Graph(Graph, Vec<u64>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Graph {
+    Spark,
+    Bar,
+    Braille,
+}
+
+// the width of a graph when the format does not specify one
+const GRAPH_WIDTH: usize = 10;
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const EIGHTHS: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+// braille dots from the bottom up, for the left and right columns of a character
+const BRAILLE_LEFT: [u32; 4] = [0x40, 0x04, 0x02, 0x01];
+const BRAILLE_RIGHT: [u32; 4] = [0x80, 0x20, 0x10, 0x08];
+
+fn scale(x: u64, max: usize) -> usize {
+    (x.min(100) as usize * max + 50) / 100
+}
+
+// a single vertical bar for a percent
+pub fn spark(pct: u64) -> char {
+    SPARKS[scale(pct, 7)]
+}
+
+impl Graph {
+    fn render(&self, samples: &[u64], width: usize) -> String {
+        match self {
+            Self::Spark => {
+                let samples = &samples[samples.len().saturating_sub(width)..];
+                let mut res = " ".repeat(width - samples.len());
+                res.extend(samples.iter().map(|x| SPARKS[scale(*x, 7)]));
+                res
+            }
+            Self::Bar => {
+                let eighths = scale(samples.last().copied().unwrap_or_default(), width * 8);
+                let mut res = "█".repeat(eighths / 8);
+                if eighths / 8 < width {
+                    res.push(EIGHTHS[eighths % 8]);
+                    res.push_str(&" ".repeat(width - eighths / 8 - 1));
+                }
+                res
+            }
+            Self::Braille => {
+                let samples = &samples[samples.len().saturating_sub(width * 2)..];
+                let mut padded = vec![None; width * 2 - samples.len()];
+                padded.extend(samples.iter().map(|x| Some(scale(*x, 4))));
+
+                padded
+                    .chunks(2)
+                    .map(|pair| {
+                        let dots = |x: Option<usize>, column: &[u32; 4]| {
+                            column.iter().take(x.unwrap_or_default()).sum::<u32>()
+                        };
+                        char::from_u32(
+                            0x2800 + dots(pair[0], &BRAILLE_LEFT) + dots(pair[1], &BRAILLE_RIGHT),
+                        )
+                        .unwrap_or(' ')
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Self::Integer(value as i64)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        Self::Integer(value as i64)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Text(s) => write!(f, "{}", s),
+            Self::Integer(i) => write!(f, "{}", i),
+            Self::Float(x, Some(precision)) => write!(f, "{:.*}", precision, x),
+            Self::Float(x, None) => write!(f, "{}", x),
+            Self::Bytes(x) => write!(f, "{}", convert(*x)),
+            Self::Rate(x) => write!(f, "{}/s", convert(*x)),
+            Self::Graph(graph, samples) => write!(f, "{}", graph.render(samples, GRAPH_WIDTH)),
+        }
+    }
+}
+
+impl Value {
+    // empty values are replaced by the default of a placeholder
+    fn is_empty(&self) -> bool {
+        match self {
+            Self::Text(s) => s.is_empty(),
+            _ => self.number().is_some_and(f64::is_nan),
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match self {
+            Self::Text(_) | Self::Graph(..) => None,
+            Self::Integer(i) => Some(*i as f64),
+            Self::Float(x, _) | Self::Bytes(x) | Self::Rate(x) => Some(*x),
+        }
+    }
+}
+
+const UNITS: &[(&str, f64)] = &[
+    ("B", 1.0),
+    ("kB", 1e3),
+    ("KB", 1e3),
+    ("MB", 1e6),
+    ("GB", 1e9),
+    ("TB", 1e12),
+    ("PB", 1e15),
+    ("KiB", 1024.0),
+    ("MiB", 1048576.0),
+    ("GiB", 1073741824.0),
+    ("TiB", 1099511627776.0),
+    ("PiB", 1125899906842624.0),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Spec {
+    fill: Option<char>,
+    align: Option<Align>,
+    width: Option<usize>,
+    precision: Option<usize>,
+    unit: Option<(&'static str, f64)>,
+}
+
+impl Spec {
+    fn apply(&self, value: &Value) -> String {
+        // the width of a graph is the number of characters drawn, there is nothing to pad
+        if let Value::Graph(graph, samples) = value {
+            return graph.render(samples, self.width.unwrap_or(GRAPH_WIDTH));
+        }
+
+        let text = match (value.number(), self.unit) {
+            (Some(x), Some((unit, divisor))) => {
+                let x = x / divisor;
+                let x = match self.precision {
+                    Some(precision) => format!("{:.*}", precision, x),
+                    None => format!("{:.2}", x).parse::<f64>().unwrap_or(x).to_string(),
+                };
+                if matches!(value, Value::Rate(_)) {
+                    format!("{} {}/s", x, unit)
+                } else {
+                    format!("{} {}", x, unit)
+                }
+            }
+            _ => match (value, self.precision) {
+                (Value::Text(s), Some(precision)) => s.chars().take(precision).collect(),
+                (Value::Integer(_) | Value::Float(..), Some(precision)) => {
+                    format!("{:.*}", precision, value.number().unwrap_or_default())
+                }
+                _ => value.to_string(),
+            },
+        };
+
+        let align = self.align.unwrap_or(if matches!(value, Value::Text(_)) {
+            Align::Left
+        } else {
+            Align::Right
+        });
+
+        self.pad(text, align)
+    }
+
+    fn pad(&self, text: String, align: Align) -> String {
+        let len = text.chars().count();
+        let width = self.width.unwrap_or_default();
+        if len >= width {
+            return text;
+        }
+
+        let fill = self.fill.unwrap_or(' ');
+        let padding = width - len;
+        let (left, right) = match align {
+            Align::Left => (0, padding),
+            Align::Right => (padding, 0),
+            Align::Center => (padding / 2, padding - padding / 2),
+        };
+
+        let mut res = String::new();
+        res.extend(std::iter::repeat_n(fill, left));
+        res.push_str(&text);
+        res.extend(std::iter::repeat_n(fill, right));
+        res
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    name: String,
+    spec: Spec,
+    // shown when the value is missing or empty
+    default: Option<Vec<Token>>,
+    raw: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    name: String,
+    negate: bool,
+    comparison: Option<(Comparison, String)>,
+}
+
+impl Condition {
+    fn eval(&self, value: Option<&Value>) -> bool {
+        let res = match (value, &self.comparison) {
+            (None, _) => false,
+            (Some(value), None) => !value.is_empty() && value.number() != Some(0.0),
+            (Some(value), Some((comparison, operand))) => {
+                let ordering = match (value.number(), operand.parse::<f64>()) {
+                    (Some(x), Ok(y)) => x.partial_cmp(&y),
+                    _ => Some(value.to_string().as_str().cmp(operand.as_str())),
+                };
+
+                ordering.is_some_and(|o| match comparison {
+                    Comparison::Eq => o.is_eq(),
+                    Comparison::Ne => o.is_ne(),
+                    Comparison::Gt => o.is_gt(),
+                    Comparison::Ge => o.is_ge(),
+                    Comparison::Lt => o.is_lt(),
+                    Comparison::Le => o.is_le(),
+                })
+            }
+        };
+
+        res != self.negate
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Literal(String),
+    Field(Field),
+    // the older `%name` style of placeholder, which is left alone if there is no such value
+    Legacy(String),
+    Conditional(Condition, Vec<Token>),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Template(Vec<Token>);
+
+fn is_ident(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            chars: source.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn error(&self, msg: &str) -> anyhow::Error {
+        anyhow!(
+            "Invalid format '{}': {} at column {}",
+            self.source,
+            msg,
+            self.pos + 1
+        )
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn ident(&mut self) -> String {
+        let mut res = String::new();
+        while let Some(c) = self.peek().filter(|c| is_ident(*c)) {
+            res.push(c);
+            self.pos += 1;
+        }
+        res
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let mut res = String::new();
+        while let Some(c) = self.peek().filter(char::is_ascii_digit) {
+            res.push(c);
+            self.pos += 1;
+        }
+        res.parse().ok()
+    }
+
+    // nested templates end at the first '}', which is left for the caller
+    fn template(&mut self, nested: bool) -> Result<Vec<Token>> {
+        let mut tokens = Vec::new();
+        let mut literal = String::new();
+
+        while let Some(c) = self.peek() {
+            match c {
+                '{' if self.peek_at(1) == Some('{') => {
+                    literal.push('{');
+                    self.pos += 2;
+                }
+                '}' if !nested && self.peek_at(1) == Some('}') => {
+                    literal.push('}');
+                    self.pos += 2;
+                }
+                '{' => {
+                    if !literal.is_empty() {
+                        tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                    }
+                    if self.peek_at(1) == Some('?') {
+                        tokens.push(self.conditional()?);
+                    } else {
+                        tokens.push(Token::Field(self.field()?));
+                    }
+                }
+                '}' if nested => break,
+                '}' => return Err(self.error("unmatched '}'")),
+                '%' if self.peek_at(1).is_some_and(is_ident) => {
+                    if !literal.is_empty() {
+                        tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                    }
+                    self.pos += 1;
+                    tokens.push(Token::Legacy(self.ident()));
+                }
+                c => {
+                    literal.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(literal));
+        }
+
+        Ok(tokens)
+    }
+
+    fn field(&mut self) -> Result<Field> {
+        let start = self.pos;
+        self.pos += 1;
+
+        let name = self.ident();
+        if name.is_empty() {
+            return Err(self.error("expected a placeholder name"));
+        }
+
+        let spec = if self.peek() == Some(':') {
+            self.pos += 1;
+            self.spec()?
+        } else {
+            Spec::default()
+        };
+
+        let default = if self.peek() == Some('|') {
+            self.pos += 1;
+            Some(self.template(true)?)
+        } else {
+            None
+        };
+
+        match self.peek() {
+            Some('}') => self.pos += 1,
+            Some(c) => return Err(self.error(&format!("unexpected '{}' in placeholder", c))),
+            None => return Err(self.error("unterminated placeholder")),
+        }
+
+        Ok(Field {
+            name,
+            spec,
+            default,
+            raw: self.chars[start..self.pos].iter().collect(),
+        })
+    }
+
+    // {?name: ...}, {?!name: ...} or {?name>0: ...}
+    fn conditional(&mut self) -> Result<Token> {
+        self.pos += 2;
+
+        let negate = self.peek() == Some('!');
+        if negate {
+            self.pos += 1;
+        }
+
+        let name = self.ident();
+        if name.is_empty() {
+            return Err(self.error("expected a placeholder name"));
+        }
+
+        let comparison = match (self.peek(), self.peek_at(1)) {
+            (Some('='), Some('=')) => Some((Comparison::Eq, 2)),
+            (Some('!'), Some('=')) => Some((Comparison::Ne, 2)),
+            (Some('>'), Some('=')) => Some((Comparison::Ge, 2)),
+            (Some('<'), Some('=')) => Some((Comparison::Le, 2)),
+            (Some('='), _) => Some((Comparison::Eq, 1)),
+            (Some('>'), _) => Some((Comparison::Gt, 1)),
+            (Some('<'), _) => Some((Comparison::Lt, 1)),
+            _ => None,
+        };
+
+        let comparison = if let Some((comparison, len)) = comparison {
+            self.pos += len;
+            let mut operand = String::new();
+            while let Some(c) = self.peek().filter(|c| !matches!(c, ':' | '{' | '}')) {
+                operand.push(c);
+                self.pos += 1;
+            }
+            Some((comparison, operand.trim().to_string()))
+        } else {
+            None
+        };
+
+        match self.peek() {
+            Some(':') => self.pos += 1,
+            Some(c) => return Err(self.error(&format!("unexpected '{}' in condition", c))),
+            None => return Err(self.error("unterminated condition")),
+        }
+
+        let body = self.template(true)?;
+
+        match self.peek() {
+            Some('}') => self.pos += 1,
+            _ => return Err(self.error("unterminated condition")),
+        }
+
+        Ok(Token::Conditional(
+            Condition {
+                name,
+                negate,
+                comparison,
+            },
+            body,
+        ))
+    }
+
+    fn spec(&mut self) -> Result<Spec> {
+        let mut spec = Spec::default();
+
+        let align = |c: Option<char>| match c {
+            Some('<') => Some(Align::Left),
+            Some('^') => Some(Align::Center),
+            Some('>') => Some(Align::Right),
+            _ => None,
+        };
+
+        if let Some(a) = align(self.peek_at(1)).filter(|_| !matches!(self.peek(), Some('}' | '|')))
+        {
+            spec.fill = self.peek();
+            spec.align = Some(a);
+            self.pos += 2;
+        } else if let Some(a) = align(self.peek()) {
+            spec.align = Some(a);
+            self.pos += 1;
+        }
+
+        if spec.fill.is_none() && self.peek() == Some('0') {
+            spec.fill = Some('0');
+            self.pos += 1;
+        }
+
+        spec.width = self.number();
+
+        if self.peek() == Some('.') {
+            self.pos += 1;
+            spec.precision = Some(
+                self.number()
+                    .ok_or_else(|| self.error("expected a precision"))?,
+            );
+        }
+
+        let start = self.pos;
+        let unit = self.ident();
+        if !unit.is_empty() {
+            spec.unit = UNITS.iter().find(|x| x.0 == unit).copied();
+            if spec.unit.is_none() {
+                self.pos = start;
+                return Err(self.error(&format!("unknown unit '{}'", unit)));
+            }
+        }
+
+        Ok(spec)
+    }
+}
+
+fn render(tokens: &[Token], rules: &Rules<'_>, res: &mut String) {
+    let get = |name: &str| rules.iter().find(|x| x.0 == name).map(|x| &x.1);
+
+    for token in tokens {
+        match token {
+            Token::Literal(s) => res.push_str(s),
+            Token::Field(field) => match (get(&field.name), &field.default) {
+                (Some(value), None) => res.push_str(&field.spec.apply(value)),
+                (Some(value), Some(_)) if !value.is_empty() => {
+                    res.push_str(&field.spec.apply(value))
+                }
+                (_, Some(default)) => {
+                    let mut s = String::new();
+                    render(default, rules, &mut s);
+                    res.push_str(&field.spec.pad(s, Align::Left))
+                }
+                (None, None) => res.push_str(&field.raw),
+            },
+            Token::Legacy(name) => match get(name) {
+                Some(value) => res.push_str(&value.to_string()),
+                None => {
+                    res.push('%');
+                    res.push_str(name);
+                }
+            },
+            Token::Conditional(condition, body) => {
+                if condition.eval(get(&condition.name)) {
+                    render(body, rules, res);
+                }
+            }
+        }
+    }
+}
+
+fn fields(tokens: &[Token], res: &mut Vec<String>) {
+    for token in tokens {
+        match token {
+            Token::Literal(_) | Token::Legacy(_) => {}
+            Token::Field(field) => {
+                res.push(field.name.clone());
+                fields(field.default.as_deref().unwrap_or_default(), res);
+            }
+            Token::Conditional(condition, body) => {
+                res.push(condition.name.clone());
+                fields(body, res);
+            }
+        }
+    }
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self> {
+        Ok(Self(Parser::new(source).template(false)?))
+    }
+
+    // Formats stay the same from one update to the next, so each is only parsed once.
+    pub fn cached(source: &str) -> Result<Arc<Self>> {
+        static CACHE: Mutex<BTreeMap<String, Arc<Template>>> = Mutex::new(BTreeMap::new());
+
+        let mut cache = CACHE.lock().unwrap();
+        if let Some(template) = cache.get(source) {
+            return Ok(template.clone());
+        }
+
+        let template = Arc::new(Self::parse(source)?);
+        cache.insert(source.to_string(), template.clone());
+        Ok(template)
+    }
+
+    // the names of the `{name}` placeholders and conditions used
+    pub fn fields(&self) -> Vec<String> {
+        let mut res = Vec::new();
+        fields(&self.0, &mut res);
+        res
+    }
+
+    pub fn render(&self, rules: &Rules<'_>) -> String {
+        let mut res = String::new();
+        render(&self.0, rules, &mut res);
+        res
+    }
+}
 
 pub struct Format<'a> {
     format: String,
@@ -11,12 +649,170 @@ impl<'a> Format<'a> {
     }
 
     pub fn format(&self) -> String {
-        let mut res = self.format.clone();
+        match Template::cached(&self.format) {
+            Ok(template) => template.render(&self.rules),
+            Err(e) => e.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(source: &str, rules: &Rules<'_>) -> String {
+        Template::parse(source).unwrap().render(rules)
+    }
 
-        for rule in &self.rules {
-            res = res.replace(rule.0, &rule.1);
+    #[test]
+    fn spec() {
+        let rules: Rules = vec![
+            ("usage".into(), Value::Float(12.345, Some(2))),
+            ("name".into(), "eth0".into()),
+            ("count".into(), 7usize.into()),
+        ];
+
+        assert_eq!(render("{usage}", &rules), "12.35");
+        assert_eq!(render("{usage:>5.1}", &rules), " 12.3");
+        assert_eq!(render("{usage:<7.1}|", &rules), "12.3   |");
+        assert_eq!(render("{name:*^8}", &rules), "**eth0**");
+        assert_eq!(render("{name:.3}", &rules), "eth");
+        assert_eq!(render("{count:03}", &rules), "007");
+        assert_eq!(render("{count:4}", &rules), "   7");
+        assert_eq!(render("{name:6}|", &rules), "eth0  |");
+    }
+
+    #[test]
+    fn sparks() {
+        assert_eq!(Graph::Spark.render(&[0, 50, 100], 3), "▁▅█");
+        // fewer samples than the width are padded on the left, and more keep the newest
+        assert_eq!(Graph::Spark.render(&[0, 100], 4), "  ▁█");
+        assert_eq!(Graph::Spark.render(&[0, 100, 100], 2), "██");
+        assert_eq!(Graph::Spark.render(&[], 2), "  ");
+        assert_eq!(Graph::Spark.render(&[150], 1), "█");
+        assert_eq!(Graph::Spark.render(&[50], 0), "");
+    }
+
+    #[test]
+    fn bars() {
+        assert_eq!(Graph::Bar.render(&[100], 4), "████");
+        assert_eq!(Graph::Bar.render(&[0], 4), "    ");
+        assert_eq!(Graph::Bar.render(&[50], 3), "█▌ ");
+        // only the newest sample is drawn
+        assert_eq!(Graph::Bar.render(&[100, 25], 4), "█   ");
+        assert_eq!(Graph::Bar.render(&[], 2), "  ");
+        assert_eq!(Graph::Bar.render(&[100], 0), "");
+    }
+
+    #[test]
+    fn braille() {
+        assert_eq!(Graph::Braille.render(&[100, 100], 1), "⣿");
+        assert_eq!(Graph::Braille.render(&[100, 0], 1), "⡇");
+        // a single sample is the right column, with nothing to its left
+        assert_eq!(Graph::Braille.render(&[100], 1), "⢸");
+        assert_eq!(Graph::Braille.render(&[25, 50], 1), "⣠");
+        assert_eq!(Graph::Braille.render(&[100], 2), "⠀⢸");
+        assert_eq!(Graph::Braille.render(&[0, 100, 100], 1), "⣿");
+        assert_eq!(Graph::Braille.render(&[100], 0), "");
+    }
+
+    #[test]
+    fn graph_width() {
+        let rules: Rules = vec![("spark".into(), Value::Graph(Graph::Spark, vec![100]))];
+
+        assert_eq!(render("{spark}", &rules).chars().count(), GRAPH_WIDTH);
+        assert_eq!(render("[{spark:3}]", &rules), "[  █]");
+    }
+
+    #[test]
+    fn escapes() {
+        let rules: Rules = vec![("usage".into(), 1usize.into())];
+
+        assert_eq!(render("{{usage}}", &rules), "{usage}");
+        assert_eq!(render("{{{usage}}}", &rules), "{1}");
+        assert_eq!(render("}} {{", &rules), "} {");
+    }
+
+    #[test]
+    fn units() {
+        let rules: Rules = vec![
+            ("size".into(), Value::Bytes(1536.0)),
+            ("rate".into(), Value::Rate(2e6)),
+            ("count".into(), 2500usize.into()),
+        ];
+
+        assert_eq!(render("{size:KiB}", &rules), "1.5 KiB");
+        assert_eq!(render("{size:.2kB}", &rules), "1.54 kB");
+        assert_eq!(render("{rate:.1MB}", &rules), "2.0 MB/s");
+        assert_eq!(render("{count:kB}", &rules), "2.5 kB");
+    }
+
+    #[test]
+    fn defaults() {
+        let rules: Rules = vec![("empty".into(), "".into()), ("name".into(), "eth0".into())];
+
+        assert_eq!(render("{missing|none}", &rules), "none");
+        assert_eq!(render("{empty|none}", &rules), "none");
+        assert_eq!(render("{name|none}", &rules), "eth0");
+        assert_eq!(render("{missing|{name} up}", &rules), "eth0 up");
+        assert_eq!(render("{missing|}", &rules), "");
+        // without a default, unknown placeholders are left as they are
+        assert_eq!(render("{missing:>4}", &rules), "{missing:>4}");
+    }
+
+    #[test]
+    fn conditionals() {
+        let rules: Rules = vec![
+            ("count".into(), 3usize.into()),
+            ("zero".into(), 0usize.into()),
+        ];
+
+        assert_eq!(render("{?count:has {count}}", &rules), "has 3");
+        assert_eq!(render("{?zero:shown}", &rules), "");
+        assert_eq!(render("{?!zero:hidden}", &rules), "hidden");
+        assert_eq!(render("{?count>=3:many}", &rules), "many");
+        assert_eq!(render("{?count<3:few}", &rules), "");
+        assert_eq!(render("{?missing:shown}", &rules), "");
+    }
+
+    #[test]
+    fn legacy() {
+        let rules: Rules = vec![("1".into(), "one".into()), ("15".into(), "fifteen".into())];
+
+        assert_eq!(render("%1 %15", &rules), "one fifteen");
+        assert_eq!(render("%2 and 100%", &rules), "%2 and 100%");
+    }
+
+    #[test]
+    fn fields() {
+        let template = Template::parse("{a} %b {c|{d}} {?e:{f}}").unwrap();
+        assert_eq!(template.fields(), vec!["a", "c", "d", "e", "f"]);
+    }
+
+    #[test]
+    fn errors() {
+        for source in [
+            "{",
+            "}",
+            "{}",
+            "{usage",
+            "{usage:.}",
+            "{usage:parsecs}",
+            "{usage!}",
+            "{?usage}",
+            "{?usage:x",
+            "{a|{b}",
+        ] {
+            assert!(Template::parse(source).is_err(), "{}", source);
         }
 
-        res
+        assert_eq!(
+            Template::parse("ab}").unwrap_err().to_string(),
+            "Invalid format 'ab}': unmatched '}' at column 3"
+        );
+        assert_eq!(
+            Template::parse("{usage:>5.1GB!}").unwrap_err().to_string(),
+            "Invalid format '{usage:>5.1GB!}': unexpected '!' in placeholder at column 14"
+        );
     }
 }
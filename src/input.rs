@@ -1,13 +1,116 @@
 use crate::{
     bar::{Click, NAME_PAGE_DOWN, NAME_PAGE_UP},
-    config::Config,
-    state::ProtectedState,
+    collectors::command::{command, i3blocks},
+    config::{ClickAction, CommandItem, Config, ConfigItem, PageChange},
+    state::{ProtectedState, State},
 };
-use tokio::io::AsyncReadExt;
+use tokio::{
+    io::AsyncReadExt,
+    sync::{mpsc::UnboundedSender, watch},
+};
+
+fn change_page(state: &mut State, config: &Config, change: PageChange) {
+    let pages = config.pages().len();
+
+    match change {
+        PageChange::Next => {
+            if state.page + 1 < pages {
+                state.page += 1
+            }
+        }
+        PageChange::Previous => {
+            if state.page > 0 {
+                state.page -= 1
+            }
+        }
+        PageChange::Page(page) => {
+            if page < pages {
+                state.page = page
+            }
+        }
+    }
+}
+
+fn toggle(state: &mut State, name: String) {
+    if state.opened.contains(&name) {
+        let mut v = Vec::new();
+        for i in &state.opened {
+            if *i != name {
+                v.push(i.clone())
+            }
+        }
+        state.opened.clear();
+        state.opened.append(&mut v);
+    } else {
+        state.opened.push(name);
+    }
+}
+
+// Click commands run through the shell, with the block's environment, the page shown in YAIB_PAGE
+// and the click in YAIB_BUTTON and the other YAIB_ variables.
+fn run_command(item: &ConfigItem, page: usize, click: &Click, line: &str) -> anyhow::Result<()> {
+    let mut child = command(item, line, true)?
+        .envs([
+            ("YAIB_PAGE", page.to_string()),
+            ("YAIB_BUTTON", click.button.to_string()),
+            ("YAIB_MODIFIERS", click.modifiers.join(" ")),
+            ("YAIB_X", click.x.to_string()),
+            ("YAIB_Y", click.y.to_string()),
+            ("YAIB_RELATIVE_X", click.relative_x.to_string()),
+            ("YAIB_RELATIVE_Y", click.relative_y.to_string()),
+            ("YAIB_WIDTH", click.width.to_string()),
+            ("YAIB_HEIGHT", click.height.to_string()),
+        ])
+        // stdin and stdout carry the i3bar protocol, so they must not be inherited
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .spawn()?;
 
-pub async fn manage_clicks(state: ProtectedState, config: Config) {
+    tokio::spawn(async move { child.wait().await });
+    Ok(())
+}
+
+fn run_action(
+    state: &mut State,
+    config: &Config,
+    commands: &UnboundedSender<CommandItem>,
+    click: &Click,
+    item: &ConfigItem,
+    action: ClickAction,
+) {
+    if let Some(command) = &action.command {
+        if let Err(e) = run_command(item, state.page, click, command) {
+            eprintln!(
+                "Could not run '{}' for block '{}': {}",
+                command, click.name, e
+            );
+        }
+    }
+
+    if action.formats.is_some() {
+        let count = config.item(&click.name).map_or(0, |x| x.formats().len());
+        let format = state.formats.entry(click.name.clone()).or_default();
+        *format = (*format + 1) % (count + 1);
+    }
+
+    if let Ok(Some(change)) = action.page_change() {
+        change_page(state, config, change);
+    }
+
+    if let Some(message) = action.message {
+        let _ = commands.send(message);
+    }
+}
+
+pub async fn manage_clicks(
+    state: ProtectedState,
+    config: watch::Receiver<Config>,
+    commands: UnboundedSender<CommandItem>,
+    redraw: UnboundedSender<()>,
+    rerun: UnboundedSender<Click>,
+) {
     let mut v = Vec::with_capacity(4096);
-    while let Ok(_) = tokio::io::stdin().read_buf(&mut v).await {
+    while tokio::io::stdin().read_buf(&mut v).await.is_ok() {
         let mut lock = state.lock().await;
 
         if v.len() > 2 && v[0] as char == '[' && v[1] as char == '\n' {
@@ -25,32 +128,29 @@ pub async fn manage_clicks(state: ProtectedState, config: Config) {
         }
 
         if let Ok(click) = serde_json::from_slice::<Click>(&v) {
+            let config = config.borrow().clone();
             match click.name.as_str() {
-                NAME_PAGE_UP => {
-                    if lock.page < config.pages().len() - 1 {
-                        lock.page += 1
-                    }
-                }
-                NAME_PAGE_DOWN => {
-                    if lock.page > 0 {
-                        lock.page -= 1
-                    }
-                }
+                NAME_PAGE_UP => change_page(&mut lock, &config, PageChange::Next),
+                NAME_PAGE_DOWN => change_page(&mut lock, &config, PageChange::Previous),
                 _ => {
-                    if lock.opened.contains(&click.name) {
-                        let mut v = Vec::new();
-                        for i in &lock.opened {
-                            if *i != click.name {
-                                v.push(i.clone())
-                            }
-                        }
-                        lock.opened.clear();
-                        lock.opened.append(&mut v);
+                    if let Some((item, action)) = config
+                        .item(&click.name)
+                        .and_then(|item| item.click_action(&click).map(|x| (item, x)))
+                    {
+                        run_action(&mut lock, &config, &commands, &click, &item, action);
+                    } else if config
+                        .item(&click.name)
+                        .is_some_and(|item| i3blocks(&item) && !item.persistent())
+                    {
+                        // i3blocks scripts handle their own clicks, being run again with them
+                        let _ = rerun.send(click);
                     } else {
-                        lock.opened.push(click.name);
+                        toggle(&mut lock, click.name);
                     }
                 }
             }
+            // blocks are otherwise only drawn when they are next collected
+            let _ = redraw.send(());
             v = Vec::new();
         }
     }
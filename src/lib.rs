@@ -1,7 +1,12 @@
+pub mod app;
 pub mod bar;
+pub mod check;
 pub mod collectors;
 pub mod config;
 pub mod formatter;
 pub mod input;
+pub mod reload;
 pub mod state;
 pub mod unix;
+
+pub use app::run;
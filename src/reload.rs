@@ -0,0 +1,133 @@
+use crate::{collectors::Registry, config::Config, state::ProtectedState};
+use anyhow::{anyhow, Result};
+use nix::{
+    errno::Errno,
+    sys::inotify::{AddWatchFlags, InitFlags, Inotify},
+};
+use std::path::{Path, PathBuf};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::{
+        mpsc::{unbounded_channel, UnboundedSender},
+        watch,
+    },
+};
+
+// Editors commonly replace the file instead of writing it, so the directory is watched instead.
+// If the file is a symlink, the directory of its target is watched as well.
+fn watch_file(filename: PathBuf, s: UnboundedSender<()>) -> Result<()> {
+    let mut paths = vec![filename.clone()];
+    if let Ok(target) = std::fs::canonicalize(&filename) {
+        if target != filename {
+            paths.push(target);
+        }
+    }
+
+    let inotify = Inotify::init(InitFlags::IN_CLOEXEC)?;
+
+    let mut watches = Vec::new();
+    for path in paths {
+        let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+            continue;
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+
+        let flags =
+            AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_MOVED_TO | AddWatchFlags::IN_CREATE;
+        if let Ok(wd) = inotify.add_watch(dir, flags) {
+            watches.push((wd, name.to_os_string()));
+        }
+    }
+
+    if watches.is_empty() {
+        return Err(anyhow!(
+            "Could not watch configuration file '{}'",
+            filename.display()
+        ));
+    }
+
+    loop {
+        let events = match inotify.read_events() {
+            Ok(events) => events,
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        for event in events {
+            if watches
+                .iter()
+                .any(|x| x.0 == event.wd && Some(&x.1) == event.name.as_ref())
+                && s.send(()).is_err()
+            {
+                return Ok(());
+            }
+        }
+    }
+}
+
+pub async fn reload(
+    filename: &Path,
+    registry: &Registry,
+    config: &watch::Sender<Config>,
+    state: ProtectedState,
+) {
+    let new = Config::load(filename.to_path_buf()).and_then(|c| c.validate(registry).map(|_| c));
+
+    let mut lock = state.lock().await;
+    match new {
+        Ok(new) => {
+            // stay on the page showing the same blocks, if there still is one
+            let old = config.borrow().pages();
+            let names = old
+                .get(lock.page)
+                .map(|x| x.items().iter().map(|x| x.name.clone()).collect::<Vec<_>>())
+                .unwrap_or_default();
+            let pages = new.pages();
+            lock.page = pages
+                .iter()
+                .position(|page| page.items().iter().any(|x| names.contains(&x.name)))
+                .unwrap_or(lock.page.min(pages.len().saturating_sub(1)));
+
+            lock.config_error = None;
+            config.send_replace(new);
+        }
+        Err(e) => {
+            eprintln!("Could not reload configuration: {}", e);
+            lock.config_error = Some(e.to_string());
+        }
+    }
+}
+
+pub async fn manage_reload(
+    filename: PathBuf,
+    registry: Registry,
+    config: watch::Sender<Config>,
+    state: ProtectedState,
+) -> Result<()> {
+    let (s, mut r) = unbounded_channel();
+    let f = filename.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = watch_file(f, s) {
+            eprintln!("{}", e);
+        }
+    });
+
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => {}
+            Some(_) = r.recv() => {
+                // saving a file is often several events; only reload once they have settled
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                while r.try_recv().is_ok() {}
+            }
+        }
+
+        reload(&filename, &registry, &config, state.clone()).await;
+    }
+}
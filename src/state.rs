@@ -1,11 +1,40 @@
 #![allow(dead_code)]
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+};
 use tokio::sync::Mutex;
 
+// number of samples kept for the graphs of each block
+pub const HISTORY_SIZE: usize = 256;
+
 #[derive(Debug, Clone, Default)]
 pub struct State {
     pub page: usize,
     pub opened: Vec<String>,
+    pub history: BTreeMap<String, VecDeque<u64>>,
+    // index into the formats cycled through by clicking, 0 being the block's own format
+    pub formats: BTreeMap<String, usize>,
+    // set when reloading the configuration failed, and the previous one is still in use
+    pub config_error: Option<String>,
+}
+
+impl State {
+    pub fn record(&mut self, name: &str, pct: u64) -> Vec<u64> {
+        let history = self.history.entry(name.to_string()).or_default();
+        if history.len() == HISTORY_SIZE {
+            history.pop_front();
+        }
+        history.push_back(pct);
+        history.iter().copied().collect()
+    }
+
+    pub fn history(&self, name: &str) -> Vec<u64> {
+        self.history
+            .get(name)
+            .map(|x| x.iter().copied().collect())
+            .unwrap_or_default()
+    }
 }
 
 pub type ProtectedState = Arc<Mutex<State>>;